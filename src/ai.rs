@@ -0,0 +1,333 @@
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::Rng;
+use rand::seq::SliceRandom;
+use tetrix::board::Board;
+use tetrix::event::{Input, Output};
+use tetrix::shape::{Shape, Orientation, Point};
+use crate::{api, codec, shapewrap};
+
+const ORIENTATIONS: [Orientation; 4] = [Orientation::Up, Orientation::Right, Orientation::Down, Orientation::Left];
+
+// weights for what a placement leaves behind
+const HEIGHT_WEIGHT: f64 = -0.51;
+const LINES_WEIGHT: f64 = 0.76;
+const HOLES_WEIGHT: f64 = -0.36;
+const BUMPINESS_WEIGHT: f64 = -0.18;
+
+// moves that change nothing before the piece is dropped where it is
+const MAX_STUCK: u32 = 6;
+
+/// How well the computer plays: how long it waits between moves, and how
+/// often it puts a piece somewhere other than the best place it found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Difficulty {
+    pub name: &'static str,
+    pub think: Duration,
+    pub mistakes: f64
+}
+
+pub const DIFFICULTIES: &[Difficulty] = &[
+    Difficulty { name: "easy", think: Duration::from_millis(450), mistakes: 0.25 },
+    Difficulty { name: "normal", think: Duration::from_millis(220), mistakes: 0.08 },
+    Difficulty { name: "hard", think: Duration::from_millis(90), mistakes: 0.02 }
+];
+
+pub fn difficulty(name: &str) -> Option<Difficulty> {
+    DIFFICULTIES.iter().cloned().find(|d| d.name == name)
+}
+
+type Cells = Vec<Vec<bool>>;
+
+fn cells_of(b: &Board) -> Cells {
+    (0..tetrix::HEIGHT).map(|y| (0..tetrix::WIDTH).map(|x| b.0[y][x].is_some()).collect()).collect()
+}
+
+fn fits(c: &Cells, piece: &[(usize, usize)], x: usize, y: usize) -> bool {
+    piece.iter().all(|&(dx, dy)| {
+        let (px, py) = (x + dx, y + dy);
+        px < tetrix::WIDTH && py < tetrix::HEIGHT && !c[py][px]
+    })
+}
+
+/// Where a piece comes to rest in column `x`, and the board with it
+/// there and any full rows gone; None if it doesn't fit at all.
+fn place(c: &Cells, piece: &[(usize, usize)], x: usize) -> Option<(Cells, u32)> {
+    let h = piece.iter().map(|&(_, dy)| dy + 1).max()?;
+    let mut y = tetrix::HEIGHT.checked_sub(h)?;
+    if !fits(c, piece, x, y) {
+        return None;
+    }
+    while y > 0 && fits(c, piece, x, y - 1) {
+        y -= 1;
+    }
+    let mut after = c.clone();
+    for &(dx, dy) in piece {
+        after[y + dy][x + dx] = true;
+    }
+    let before = after.len();
+    after.retain(|row| !row.iter().all(|&c| c));
+    let lines = (before - after.len()) as u32;
+    after.resize(before, vec![false; tetrix::WIDTH]);
+    Some((after, lines))
+}
+
+fn score(c: &Cells, lines: u32) -> f64 {
+    let heights: Vec<usize> = (0..tetrix::WIDTH)
+        .map(|x| (0..tetrix::HEIGHT).rev().find(|&y| c[y][x]).map_or(0, |y| y + 1))
+        .collect();
+    let holes: usize = (0..tetrix::WIDTH)
+        .map(|x| (0..heights[x]).filter(|&y| !c[y][x]).count())
+        .sum();
+    let bumpiness: usize = heights.windows(2).map(|w| (w[0] as isize - w[1] as isize).abs() as usize).sum();
+    HEIGHT_WEIGHT * heights.iter().sum::<usize>() as f64
+        + LINES_WEIGHT * lines as f64
+        + HOLES_WEIGHT * holes as f64
+        + BUMPINESS_WEIGHT * bumpiness as f64
+}
+
+/// Every place `shape` can go, as (orientation, column, score).
+fn placements(c: &Cells, shape: Shape) -> Vec<(Orientation, usize, f64)> {
+    let mut out: Vec<(Orientation, usize, f64)> = Vec::new();
+    for o in ORIENTATIONS.iter() {
+        let piece = shapewrap::cells(shape, *o);
+        // Up and Down of an Eye are the same thing; no need to look twice
+        if out.iter().any(|(seen, _, _)| shapewrap::cells(shape, *seen) == piece) {
+            continue;
+        }
+        for x in 0..tetrix::WIDTH {
+            if let Some((after, lines)) = place(c, &piece, x) {
+                out.push((*o, x, score(&after, lines)));
+            }
+        }
+    }
+    out
+}
+
+fn best(options: &[(Orientation, usize, f64)]) -> Option<(Orientation, usize, f64)> {
+    options.iter().cloned().fold(None, |best, o| match best {
+        Some(b) if b.2 >= o.2 => Some(b),
+        _ => Some(o)
+    })
+}
+
+/// A computer player.  It follows a game through its events like a view
+/// does, picks a place for each piece as it appears, and hands out the
+/// inputs to get it there one at a time, at its own pace.  Moves are
+/// steered by where the piece actually is, so a wall kick or a piece
+/// falling under it doesn't throw it off.
+pub struct Ai {
+    difficulty: Difficulty,
+    settled: Board,
+    active: Option<(Shape, Orientation, Point)>,
+    held: Option<Shape>,
+    next_shape: Option<Shape>,
+    // where the piece in play is going, or to swap it for the held one
+    target: Option<(Orientation, usize)>,
+    hold: bool,
+    held_this_piece: bool,
+    last_move: Instant,
+    last_seen: Option<(Orientation, usize)>,
+    stuck: u32
+}
+
+impl Ai {
+    pub fn new(difficulty: Difficulty) -> Ai {
+        Ai {
+            difficulty,
+            settled: Board::new(),
+            active: None,
+            held: None,
+            next_shape: None,
+            target: None,
+            hold: false,
+            held_this_piece: false,
+            last_move: Instant::now(),
+            last_seen: None,
+            stuck: 0
+        }
+    }
+
+    pub fn handle(&mut self, evt: &Output) {
+        match evt {
+            Output::GameStarted => *self = Ai::new(self.difficulty),
+            Output::NextShape(shape) => self.next_shape = Some(*shape),
+            Output::HeldShape(shape) => {
+                self.held = Some(*shape);
+                self.active = None;
+            },
+            Output::LineCompleted(_, board) | Output::ShapeLocked(_, board) => {
+                self.settled = *board;
+                self.active = None;
+                self.held_this_piece = false;
+            },
+            Output::ShapePosition(shape, _, orientation, from, to) => {
+                self.active = Some((*shape, *orientation, *to));
+                if from.is_none() {
+                    self.plan(*shape);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    // decides where a new piece goes, or whether to hold it
+    fn plan(&mut self, shape: Shape) {
+        let c = cells_of(&self.settled);
+        let mut options = placements(&c, shape);
+        let mut rng = rand::thread_rng();
+        let choice = if rng.gen_bool(self.difficulty.mistakes) {
+            options.choose(&mut rng).cloned()
+        } else {
+            best(&options)
+        };
+        // what would come out of the hold instead
+        let other = self.held.or(self.next_shape);
+        let swap = match (choice, other) {
+            (Some(mine), Some(other)) if !self.held_this_piece => {
+                options = placements(&c, other);
+                best(&options).map_or(false, |theirs| theirs.2 > mine.2 + 1.0)
+            },
+            _ => false
+        };
+        self.hold = swap;
+        self.target = choice.map(|(o, x, _)| (o, x));
+        self.last_seen = None;
+        self.stuck = 0;
+        self.last_move = Instant::now();
+    }
+
+    /// The next input, when it's time for one.
+    pub fn next_input(&mut self) -> Option<Input> {
+        if self.last_move.elapsed() < self.difficulty.think {
+            return None;
+        }
+        let (shape, o, p) = self.active?;
+        let (to, x) = self.target?;
+        self.last_move = Instant::now();
+        if self.hold {
+            self.hold = false;
+            self.held_this_piece = true;
+            self.target = None;
+            return Some(Input::Hold);
+        }
+        // a move that didn't take: give up on getting there.  The piece
+        // falls while it's steered, so only its turn and column count.
+        if self.last_seen == Some((o, p.x)) {
+            self.stuck += 1;
+        }
+        self.last_seen = Some((o, p.x));
+        let input = if self.stuck >= MAX_STUCK {
+            Input::Drop
+        } else if shapewrap::cells(shape, o) != shapewrap::cells(shape, to) {
+            // only ever clockwise, so a library that turns the other way still gets there
+            Input::Cw
+        } else if p.x < x {
+            Input::Right
+        } else if p.x > x {
+            Input::Left
+        } else {
+            Input::Drop
+        };
+        if let Input::Drop = input {
+            self.target = None;
+        }
+        Some(input)
+    }
+}
+
+// one bot playing game after game over the json api until it fails
+fn bot(n: usize, difficulty: Difficulty) -> io::Result<()> {
+    let stream = TcpStream::connect(("127.0.0.1", api::PORT))?;
+    stream.set_read_timeout(Some(Duration::from_millis(20)))?;
+    let mut out = stream.try_clone()?;
+    let mut lines = BufReader::new(stream);
+    let mut ai = Ai::new(difficulty);
+    let mut line = String::new();
+    writeln!(out, "{}", json::object!{"type": "Login", "name": format!("cpu-{}", n)}.dump())?;
+    writeln!(out, "{}", json::object!{"type": "Start"}.dump())?;
+    loop {
+        match lines.read_line(&mut line) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(_) if line.ends_with('\n') => {
+                let v = json::parse(&line).unwrap_or(json::JsonValue::Null);
+                line.clear();
+                if v["type"] == "Error" {
+                    log::error!("cpu-{}: {}", n, v["message"]);
+                }
+                if let Some(evt) = codec::output_from_json(&v) {
+                    ai.handle(&evt);
+                    if let Output::GameOver = evt {
+                        writeln!(out, "{}", json::object!{"type": "Start"}.dump())?;
+                    }
+                }
+            },
+            Ok(_) => {},
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {},
+            Err(e) => return Err(e)
+        }
+        if let Some(input) = ai.next_input() {
+            writeln!(out, "{}", json::object!{"type": "Input", "input": codec::input_name(input)}.dump())?;
+        }
+    }
+}
+
+/// Puts `bots` computer players on this machine's json api, each playing
+/// as a guest called cpu-1, cpu-2 and so on, one game after another,
+/// until the process is stopped.  Their games are saved like anyone's.
+pub fn soak(bots: usize, difficulty: Difficulty) {
+    let threads: Vec<_> = (1..=bots).map(|n| thread::spawn(move || {
+        if let Err(e) = bot(n, difficulty) {
+            log::error!("cpu-{} stopped: {}", n, e);
+        }
+    })).collect();
+    for t in threads {
+        let _ = t.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(rows: &[&str]) -> Cells {
+        let mut c = vec![vec![false; tetrix::WIDTH]; tetrix::HEIGHT];
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, ch) in row.chars().enumerate() {
+                c[y][x] = ch == '#';
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn an_eye_fills_the_well() {
+        let c = board(&["#########.", "#########.", "#########.", "#########."]);
+        let (o, x, _) = best(&placements(&c, Shape::Eye)).unwrap();
+        assert_eq!(x, 9);
+        assert_eq!(shapewrap::cells(Shape::Eye, o).len(), 4);
+        assert!(shapewrap::cells(Shape::Eye, o).iter().all(|&(dx, _)| dx == 0));
+        let (after, lines) = place(&c, &shapewrap::cells(Shape::Eye, o), x).unwrap();
+        assert_eq!(lines, 4);
+        assert!(after.iter().all(|row| row.iter().all(|&c| !c)));
+    }
+
+    #[test]
+    fn a_square_lands_flat_rather_than_leave_holes() {
+        let c = board(&["##..######"]);
+        let (_, x, _) = best(&placements(&c, Shape::Square)).unwrap();
+        assert_eq!(x, 2);
+    }
+
+    #[test]
+    fn pieces_stop_on_the_stack() {
+        let c = board(&["#.........", "#........."]);
+        let (after, lines) = place(&c, &shapewrap::cells(Shape::Square, Orientation::Up), 0).unwrap();
+        assert_eq!(lines, 0);
+        assert!(after[2][0] && after[3][0] && after[2][1] && after[3][1]);
+        assert!(!after[1][1]);
+    }
+}
@@ -0,0 +1,186 @@
+use std::io::prelude::*;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+use bufstream::BufStream;
+use json::JsonValue;
+use crate::codec;
+use crate::play;
+use crate::prefs::{self, Prefs};
+use crate::transport;
+use crate::users::{self, Player};
+
+pub const PORT: u16 = 2424;
+const VERSION: u32 = 1;
+// wrong passwords before the connection is dropped, as over telnet
+const LOGIN_TRIES: u32 = 3;
+// how long a client that isn't reading gets before it's given up on
+const STALL: Duration = Duration::from_secs(30);
+const WAIT: Duration = Duration::from_millis(20);
+
+// The socket doesn't block, so a full send buffer comes back as
+// `WouldBlock`; that's waited out, anything else is the client gone.
+fn retry<T>(mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let start = Instant::now();
+    loop {
+        match op() {
+            Err(ref e) if !transport::gone(e) && start.elapsed() < STALL => thread::sleep(WAIT),
+            r => return r
+        }
+    }
+}
+
+fn send(s: &mut BufStream<TcpStream>, v: JsonValue) -> io::Result<()> {
+    let mut line = v.dump().into_bytes();
+    line.push(b'\n');
+    let mut rest = &line[..];
+    while !rest.is_empty() {
+        let n = retry(|| s.write(rest))?;
+        rest = &rest[n..];
+    }
+    Ok(())
+}
+
+fn error(s: &mut BufStream<TcpStream>, message: &str) -> io::Result<()> {
+    send(s, json::object!{"type": "Error", "message": message})
+}
+
+fn login(s: &mut BufStream<TcpStream>, v: &JsonValue, failures: &mut u32) -> io::Result<Option<Player>> {
+    let name = match v["name"].as_str() {
+        Some(name) if !name.trim().is_empty() => name.trim(),
+        _ => {
+            error(s, "Login needs a name")?;
+            return Ok(None);
+        }
+    };
+    let registered = users::is_registered(name);
+    if registered && !users::verify(name, v["password"].as_str().unwrap_or("")) {
+        log::info!("{} failed a password check over the api", name);
+        *failures += 1;
+        error(s, "Wrong password")?;
+        return Ok(None);
+    }
+    log::info!("{} logged in over the api{}", name, if registered { "" } else { " as a guest" });
+    send(s, json::object!{"type": "Welcome", "name": name, "registered": registered})?;
+    Ok(Some(Player { name: name.to_string(), registered }))
+}
+
+// what one line from the client asks for; false to hang up
+fn handle(s: &mut BufStream<TcpStream>, v: &JsonValue, player: &mut Option<Player>, failures: &mut u32, game: &mut Option<play::Game>) -> io::Result<bool> {
+    match (v["type"].as_str(), player.as_ref()) {
+        (Some("Login"), None) => {
+            *player = login(s, v, failures)?;
+            if *failures >= LOGIN_TRIES {
+                return Ok(false);
+            }
+        },
+        (Some("Login"), Some(_)) => error(s, "Already logged in")?,
+        (Some("Quit"), _) => return Ok(false),
+        (_, None) => error(s, "Log in first")?,
+        (Some("Start"), Some(p)) => {
+            if game.as_ref().map_or(false, |g| !g.over) {
+                error(s, "A game is already running")?;
+                return Ok(true);
+            }
+            let level = v["level"].as_u8().unwrap_or(0).min(prefs::MAX_START_LEVEL);
+            let prefs = Prefs { start_level: level, ..prefs::load(p) };
+            let mut g = play::Game::new(p, &prefs);
+            g.start();
+            *game = Some(g);
+        },
+        (Some("Input"), Some(_)) => match (game.as_ref(), v["input"].as_str().and_then(codec::parse_input)) {
+            (Some(g), Some(input)) if !g.over => g.send(input),
+            (_, None) => error(s, "Unknown input")?,
+            _ => error(s, "No game running")?
+        },
+        _ => error(s, "Unknown message")?
+    }
+    Ok(true)
+}
+
+// Sends what the game has done and answers a line from the client if a
+// whole one has come in.  False, or an error, to hang up.
+fn pass(s: &mut BufStream<TcpStream>, line: &mut Vec<u8>, player: &mut Option<Player>, failures: &mut u32, game: &mut Option<play::Game>) -> io::Result<bool> {
+    if let Some(g) = game.as_mut() {
+        for evt in g.poll() {
+            if let Some(v) = codec::output_to_json(&evt) {
+                send(s, v)?;
+            }
+        }
+    }
+    // a line can come in pieces; what's read stays in `line` until the rest does
+    let more = match s.read_until(b'\n', line) {
+        Ok(0) => false,
+        Ok(_) if line.ends_with(b"\n") => {
+            let text = String::from_utf8_lossy(line).to_string();
+            line.clear();
+            match json::parse(&text) {
+                Ok(v) => handle(s, &v, player, failures, game)?,
+                Err(_) => {
+                    error(s, "Not JSON")?;
+                    true
+                }
+            }
+        },
+        Ok(_) => true,
+        Err(ref e) if !transport::gone(e) => true,
+        Err(_) => false
+    };
+    retry(|| s.flush())?;
+    Ok(more)
+}
+
+fn serve(stream: TcpStream) {
+    stream.set_nonblocking(true).unwrap();
+    let mut s = BufStream::new(stream);
+    let mut player = None;
+    let mut failures = 0;
+    let mut game: Option<play::Game> = None;
+    let mut line = Vec::new();
+    let hello = send(&mut s, json::object!{"type": "Hello", "version": VERSION});
+    if hello.and_then(|_| retry(|| s.flush())).is_ok() {
+        while pass(&mut s, &mut line, &mut player, &mut failures, &mut game).unwrap_or(false) {
+            thread::sleep(WAIT);
+        }
+    }
+    if let Some(g) = game.as_mut().filter(|g| !g.over) {
+        g.quit();
+    }
+    log::info!("api client {} disconnected", player.map_or("(not logged in)".to_string(), |p| p.name));
+}
+
+/// Serves line-delimited JSON for bots and home-made clients on `PORT`
+/// until the process ends.
+/// Every line each way is one object with a `"type"`.
+///
+/// From the client:
+///
+/// * `{"type": "Login", "name": "bot", "password": "..."}`.  The password
+///   is only needed for registered names; anyone else plays as a guest.
+///   The connection is closed after three wrong passwords.
+/// * `{"type": "Start", "level": 0}` starts a game, `level` optional.
+/// * `{"type": "Input", "input": "Left"}`, one of Left, Right, Drop,
+///   Hold, Cw and Ccw.
+/// * `{"type": "Quit"}` ends any game and the connection.
+///
+/// From the server: `Hello` on connecting, `Welcome` once logged in,
+/// `Error` with a `message` for anything refused, and the game's own
+/// events as the replay files have them (`codec::output_to_json`):
+/// GameStarted, BoardUpdate, ShapePosition, NextShape, HeldShape,
+/// LineCompleted, ShapeLocked, ScoreUpdate and GameOver.
+pub fn listen() {
+    let listener = match TcpListener::bind(("0.0.0.0", PORT)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("not serving the json api on port {}: {}", PORT, e);
+            return;
+        }
+    };
+    log::info!("Starting json api on 0.0.0.0 at port {}", PORT);
+    for stream in listener.incoming() {
+        if let Ok(stream) = stream {
+            thread::spawn(move || serve(stream));
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use json::JsonValue;
+use tetrix::board::Board;
+use tetrix::event::{Input, Output};
+use tetrix::shape::{Shape, Orientation, Point};
+
+pub const SHAPES: [Shape; 7] = [
+    Shape::Eye, Shape::El, Shape::ElInv, Shape::Zee, Shape::ZeeInv, Shape::Square, Shape::Tee
+];
+
+pub fn shape_name(s: Shape) -> &'static str {
+    match s {
+        Shape::Eye => "Eye",
+        Shape::El => "El",
+        Shape::ElInv => "ElInv",
+        Shape::Zee => "Zee",
+        Shape::ZeeInv => "ZeeInv",
+        Shape::Square => "Square",
+        Shape::Tee => "Tee"
+    }
+}
+
+pub fn parse_shape(s: &str) -> Option<Shape> {
+    match s {
+        "Eye" => Some(Shape::Eye),
+        "El" => Some(Shape::El),
+        "ElInv" => Some(Shape::ElInv),
+        "Zee" => Some(Shape::Zee),
+        "ZeeInv" => Some(Shape::ZeeInv),
+        "Square" => Some(Shape::Square),
+        "Tee" => Some(Shape::Tee),
+        _ => None
+    }
+}
+
+pub fn orientation_name(o: Orientation) -> &'static str {
+    match o {
+        Orientation::Up => "Up",
+        Orientation::Down => "Down",
+        Orientation::Left => "Left",
+        Orientation::Right => "Right"
+    }
+}
+
+pub fn parse_orientation(s: &str) -> Option<Orientation> {
+    match s {
+        "Up" => Some(Orientation::Up),
+        "Down" => Some(Orientation::Down),
+        "Left" => Some(Orientation::Left),
+        "Right" => Some(Orientation::Right),
+        _ => None
+    }
+}
+
+/// The moves a client can make; starting and ending games are up to the
+/// frontend.
+pub fn input_name(i: Input) -> &'static str {
+    match i {
+        Input::Left => "Left",
+        Input::Right => "Right",
+        Input::Drop => "Drop",
+        Input::Hold => "Hold",
+        Input::Cw => "Cw",
+        Input::Ccw => "Ccw",
+        _ => "Other"
+    }
+}
+
+pub fn parse_input(s: &str) -> Option<Input> {
+    match s {
+        "Left" => Some(Input::Left),
+        "Right" => Some(Input::Right),
+        "Drop" => Some(Input::Drop),
+        "Hold" => Some(Input::Hold),
+        "Cw" => Some(Input::Cw),
+        "Ccw" => Some(Input::Ccw),
+        _ => None
+    }
+}
+
+// one letter per cell keeps a full board down to a couple hundred bytes.
+pub fn shape_letter(s: Shape) -> char {
+    match s {
+        Shape::Eye => 'I',
+        Shape::El => 'L',
+        Shape::ElInv => 'J',
+        Shape::Zee => 'Z',
+        Shape::ZeeInv => 'S',
+        Shape::Square => 'O',
+        Shape::Tee => 'T'
+    }
+}
+
+fn parse_letter(c: char) -> Option<Shape> {
+    match c {
+        'I' => Some(Shape::Eye),
+        'L' => Some(Shape::El),
+        'J' => Some(Shape::ElInv),
+        'Z' => Some(Shape::Zee),
+        'S' => Some(Shape::ZeeInv),
+        'O' => Some(Shape::Square),
+        'T' => Some(Shape::Tee),
+        _ => None
+    }
+}
+
+/// Rows bottom (y = 0) first, separated by `/`; `.` is an empty cell.
+pub fn board_to_string(b: &Board) -> String {
+    let mut out = String::with_capacity(tetrix::HEIGHT * (tetrix::WIDTH + 1));
+    for y in 0..tetrix::HEIGHT {
+        if y > 0 {
+            out.push('/');
+        }
+        for x in 0..tetrix::WIDTH {
+            match b.0[y][x] {
+                Some(shape) => out.push(shape_letter(shape)),
+                None => out.push('.')
+            }
+        }
+    }
+    out
+}
+
+pub fn parse_board(s: &str) -> Option<Board> {
+    let mut b = Board::new();
+    for (y, row) in s.split('/').enumerate() {
+        if y >= tetrix::HEIGHT {
+            return None;
+        }
+        for (x, c) in row.chars().enumerate() {
+            if x >= tetrix::WIDTH {
+                return None;
+            }
+            b.0[y][x] = parse_letter(c);
+        }
+    }
+    Some(b)
+}
+
+fn point_to_json(p: Point) -> JsonValue {
+    json::array![p.x, p.y]
+}
+
+fn parse_point(v: &JsonValue) -> Option<Point> {
+    Some(Point::new(v[0].as_usize()?, v[1].as_usize()?))
+}
+
+/// Encodes the events the client draws from.  Anything else comes back as `None`.
+pub fn output_to_json(evt: &Output) -> Option<JsonValue> {
+    let v = match evt {
+        Output::GameStarted => json::object!{"type": "GameStarted"},
+        Output::GameOver => json::object!{"type": "GameOver"},
+        Output::BoardUpdate(b) => json::object!{
+            "type": "BoardUpdate",
+            "board": board_to_string(b)
+        },
+        Output::HeldShape(shape) => json::object!{
+            "type": "HeldShape",
+            "shape": shape_name(*shape)
+        },
+        Output::NextShape(shape) => json::object!{
+            "type": "NextShape",
+            "shape": shape_name(*shape)
+        },
+        Output::LineCompleted(count, b) => json::object!{
+            "type": "LineCompleted",
+            "count": *count,
+            "board": board_to_string(b)
+        },
+        Output::ScoreUpdate(score) => json::object!{
+            "type": "ScoreUpdate",
+            "score": *score
+        },
+        Output::ShapeLocked(shape, b) => json::object!{
+            "type": "ShapeLocked",
+            "shape": shape_name(*shape),
+            "board": board_to_string(b)
+        },
+        Output::ShapePosition(shape, from_orientation, orientation, from, to) => json::object!{
+            "type": "ShapePosition",
+            "shape": shape_name(*shape),
+            "from_orientation": from_orientation.map(orientation_name),
+            "orientation": orientation_name(*orientation),
+            "from": from.map(point_to_json),
+            "to": point_to_json(*to)
+        },
+        _ => return None
+    };
+    Some(v)
+}
+
+pub fn output_from_json(v: &JsonValue) -> Option<Output> {
+    let evt = match v["type"].as_str()? {
+        "GameStarted" => Output::GameStarted,
+        "GameOver" => Output::GameOver,
+        "BoardUpdate" => Output::BoardUpdate(parse_board(v["board"].as_str()?)?),
+        "HeldShape" => Output::HeldShape(parse_shape(v["shape"].as_str()?)?),
+        "NextShape" => Output::NextShape(parse_shape(v["shape"].as_str()?)?),
+        "LineCompleted" => Output::LineCompleted(
+            v["count"].as_u64()? as _,
+            parse_board(v["board"].as_str()?)?
+        ),
+        "ScoreUpdate" => Output::ScoreUpdate(v["score"].as_u32()?),
+        "ShapeLocked" => Output::ShapeLocked(
+            parse_shape(v["shape"].as_str()?)?,
+            parse_board(v["board"].as_str()?)?
+        ),
+        "ShapePosition" => {
+            let from_orientation = match v["from_orientation"].as_str() {
+                Some(o) => Some(parse_orientation(o)?),
+                None => None
+            };
+            let from = if v["from"].is_null() { None } else { Some(parse_point(&v["from"])?) };
+            Output::ShapePosition(
+                parse_shape(v["shape"].as_str()?)?,
+                from_orientation,
+                parse_orientation(v["orientation"].as_str()?)?,
+                from,
+                parse_point(&v["to"])?
+            )
+        },
+        _ => return None
+    };
+    Some(evt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board() -> Board {
+        let mut b = Board::new();
+        b.0[0][0] = Some(Shape::Eye);
+        b.0[0][9] = Some(Shape::Tee);
+        b.0[1][4] = Some(Shape::ZeeInv);
+        b
+    }
+
+    // what a replay writes has to read back as the same event
+    #[test]
+    fn events_read_back_as_written() {
+        let events = [
+            Output::GameStarted,
+            Output::GameOver,
+            Output::BoardUpdate(board()),
+            Output::HeldShape(Shape::El),
+            Output::NextShape(Shape::ElInv),
+            Output::LineCompleted(2, board()),
+            Output::ScoreUpdate(12),
+            Output::ShapeLocked(Shape::Square, board()),
+            Output::ShapePosition(Shape::Zee, None, Orientation::Up, None, Point::new(3, 20)),
+            Output::ShapePosition(Shape::Tee, Some(Orientation::Up), Orientation::Right, Some(Point::new(3, 20)), Point::new(4, 19))
+        ];
+        for evt in events.iter() {
+            let v = output_to_json(evt).unwrap();
+            let back = output_from_json(&v).unwrap();
+            assert_eq!(output_to_json(&back), Some(v));
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use tetrix::shape::Point;
+use crate::layout::{Glyph, Layout};
+use crate::theme::Palette;
+use crate::transport::Transport;
+
+pub const ANSI_ESCAPE: &'static [u8] = &[0x1B, 0x5B];
+
+pub fn pos(s: &mut dyn Transport, p: Point) {
+    s.write(ANSI_ESCAPE).unwrap();
+    // TODO adjust to fit on board
+    s.write(format!("{};{}H", p.y, p.x).as_bytes()).unwrap();
+}
+
+/// Sets graphic attributes; `c` is everything after the `ESC [`.  An empty
+/// `c` (a `Plain` palette) writes nothing.
+pub fn sgr(s: &mut dyn Transport, c: &str) {
+    if c.is_empty() {
+        return;
+    }
+    s.write(ANSI_ESCAPE).unwrap();
+    s.write(c.as_bytes()).unwrap();
+}
+
+pub fn cursor_fwd(s: &mut dyn Transport) {
+    s.write(ANSI_ESCAPE).unwrap();
+    s.write(b"1C").unwrap();
+}
+
+fn write_char(s: &mut dyn Transport, c: char) {
+    let mut buf = [0; 4];
+    s.write(c.encode_utf8(&mut buf).as_bytes()).unwrap();
+}
+
+/// Draws `g` with its top-left corner at `p`.  `c` is an SGR sequence from
+/// the `Palette`, sans the leading `ESC [`.
+pub fn draw_glyph(s: &mut dyn Transport, g: &Glyph, p: Point, c: &str) {
+    sgr(s, c);
+    for (i, line) in g.lines.iter().enumerate() {
+        pos(s, Point::new(p.x, p.y + i));
+        for u in line {
+            match u {
+                Some(ch) => write_char(s, *ch),
+                None => cursor_fwd(s)
+            }
+        }
+    }
+}
+
+/// Blanks what `draw_glyph` drew, painting it with the background in `c`.
+pub fn clear_glyph(s: &mut dyn Transport, g: &Glyph, p: Point, c: &str) {
+    sgr(s, c);
+    for (i, line) in g.lines.iter().enumerate() {
+        pos(s, Point::new(p.x, p.y + i));
+        for u in line {
+            match u {
+                Some(_) => { s.write(b" ").unwrap(); },
+                None => cursor_fwd(s)
+            }
+        }
+    }
+}
+
+/// Switches to the alternate screen and hides the cursor, so the game
+/// neither scrolls away the player's terminal history nor has a cursor
+/// blinking around the board.
+pub fn enter_fullscreen(s: &mut dyn Transport) {
+    s.write(ANSI_ESCAPE).unwrap();
+    s.write(b"?1049h").unwrap();
+    s.write(ANSI_ESCAPE).unwrap();
+    s.write(b"?25l").unwrap();
+    s.flush().unwrap();
+}
+
+/// Undoes `enter_fullscreen` and any colors left set, leaving the terminal
+/// the way the player had it.
+pub fn leave_fullscreen(s: &mut dyn Transport) {
+    s.write(ANSI_ESCAPE).unwrap();
+    s.write(b"?25h").unwrap();
+    s.write(ANSI_ESCAPE).unwrap();
+    s.write(b"0m").unwrap();
+    s.write(ANSI_ESCAPE).unwrap();
+    s.write(b"?1049l").unwrap();
+    s.flush().unwrap();
+}
+
+pub fn cls(s: &mut dyn Transport) {
+    s.write(&[0x00, 0x1B]).unwrap();
+    s.write(b"[2J").unwrap();
+}
+
+pub fn clr(s: &mut dyn Transport, amt: usize) {
+    for _ in 0..amt {
+        s.write(b" ").unwrap();
+    }
+}
+
+pub fn draw_board(s: &mut dyn Transport, l: &Layout, pal: &Palette) {
+    let (w, h) = l.board_size();
+    let edge = "-".repeat(w);
+    if l.frame {
+        pos(s, Point::new(1, l.top - 1));
+        sgr(s, &pal.border);
+        s.write(format!("/{}\\", edge).as_bytes()).unwrap();
+    }
+    // positioned line by line; a newline on the last line would scroll
+    for i in 0..h {
+        pos(s, Point::new(1, l.top + i));
+        sgr(s, &pal.border);
+        s.write(b"|").unwrap();
+        sgr(s, &pal.field);
+        clr(s, w);
+        sgr(s, &pal.border);
+        s.write(b"|").unwrap();
+    }
+    if l.frame {
+        pos(s, Point::new(1, l.top + h));
+        s.write(format!("\\{}/", edge).as_bytes()).unwrap();
+    }
+    sgr(s, &pal.reset);
+    s.flush().unwrap();
+}
+
+pub fn draw_score(s: &mut dyn Transport, l: &Layout, score: u32, pal: &Palette) {
+    pos(s, Point::new(l.panel_x, l.lines_row));
+    sgr(s, &pal.text);
+    s.write(format!("Lines: {}", score).as_bytes()).unwrap();
+}
+
+/// `reverse` is for flashing it on a level up; a plain palette can't.
+pub fn draw_level(s: &mut dyn Transport, l: &Layout, level: u8, pal: &Palette, reverse: bool) {
+    pos(s, Point::new(l.panel_x, l.level_row));
+    sgr(s, &pal.text);
+    if reverse && !pal.reset.is_empty() {
+        sgr(s, "7m");
+    }
+    s.write(format!("Level: {}", level + 1).as_bytes()).unwrap();
+}
@@ -0,0 +1,87 @@
+use tetrix::shape::{Shape, Point};
+use crate::draw::*;
+use crate::layout::{self, Layout, ROWS};
+use crate::theme::Palette;
+use crate::transport::Transport;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cell {
+    Empty,
+    Block(Shape),
+    Ghost(Shape)
+}
+
+/// The board as the client has it on screen, one `Cell` per board cell with
+/// row 0 at the bottom.  `sync` only repaints what changed, which matters in
+/// half-block mode where one character shows two rows.
+pub struct Grid {
+    // None until painted, so the next sync draws it whatever it holds
+    shown: Vec<Option<Cell>>
+}
+
+impl Grid {
+    /// For a freshly drawn (empty) board.
+    pub fn new() -> Grid {
+        Grid { shown: vec![Some(Cell::Empty); ROWS * tetrix::WIDTH] }
+    }
+
+    pub fn invalidate(&mut self) {
+        for c in self.shown.iter_mut() {
+            *c = None;
+        }
+    }
+
+    /// Brings the screen in line with `want`, indexed `y * WIDTH + x`.
+    pub fn sync(&mut self, s: &mut dyn Transport, l: &Layout, pal: &Palette, want: &[Cell]) {
+        let w = tetrix::WIDTH;
+        if l.half {
+            for y in (0..ROWS).step_by(2) {
+                for x in 0..w {
+                    let (lower, upper) = (y * w + x, (y + 1) * w + x);
+                    if self.shown[lower] != Some(want[lower]) || self.shown[upper] != Some(want[upper]) {
+                        paint_pair(s, l, pal, x, y, want[upper], want[lower]);
+                    }
+                }
+            }
+        } else {
+            for y in 0..ROWS {
+                for x in 0..w {
+                    let i = y * w + x;
+                    if self.shown[i] != Some(want[i]) {
+                        paint_cell(s, l, pal, x, y, want[i]);
+                    }
+                }
+            }
+        }
+        for (shown, c) in self.shown.iter_mut().zip(want) {
+            *shown = Some(*c);
+        }
+    }
+}
+
+fn paint_cell(s: &mut dyn Transport, l: &Layout, pal: &Palette, x: usize, y: usize, c: Cell) {
+    let p = l.cell_pos(x, y);
+    sgr(s, pal.cell(c));
+    for (i, t) in l.cell_lines(c).iter().enumerate() {
+        pos(s, Point::new(p.x, p.y + i));
+        s.write(t.as_bytes()).unwrap();
+    }
+}
+
+// rows y and y+1 share a line: the top half is drawn in the foreground
+// color, the bottom half in the background color.
+fn paint_pair(s: &mut dyn Transport, l: &Layout, pal: &Palette, x: usize, y: usize, upper: Cell, lower: Cell) {
+    pos(s, l.cell_pos(x, y));
+    let (c, params) = match (pal.fg(upper), pal.fg(lower)) {
+        (None, None) => (' ', pal.field.clone()),
+        (Some(top), None) => (layout::UPPER_HALF, format!("0;{};{}m", top, pal.bg(Cell::Empty))),
+        (None, Some(bottom)) => (layout::LOWER_HALF, format!("0;{};{}m", bottom, pal.bg(Cell::Empty))),
+        (Some(top), Some(_)) => (layout::UPPER_HALF, format!("0;{};{}m", top, pal.bg(lower)))
+    };
+    sgr(s, &params);
+    let mut buf = [0; 4];
+    let c = c.encode_utf8(&mut buf);
+    for _ in 0..l.cell_w {
+        s.write(c.as_bytes()).unwrap();
+    }
+}
@@ -0,0 +1,550 @@
+//! Runs the game's screens without a network or a terminal.  `Mem` is a
+//! `Transport` the test types into and reads back from; `Vt` turns what was
+//! written into the 80x24 grid a VT100 would show, so tests can ask what's
+//! on screen rather than which bytes were sent.
+//!
+//! The library deals its pieces at random and on its own thread.  Tests of
+//! the view play the part of the library, handing it the events a game
+//! would send for the moves they're after; tests of a whole session type
+//! keys into a real game, each once the screen shows what it's waiting
+//! for, and read whatever piece it dealt off the screen.  Sessions keep
+//! their users and replays in a scratch directory.
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+use std::process;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tetrix::board::Board;
+use tetrix::event::Output;
+use tetrix::shape::{Shape, Orientation, Point};
+use crate::grid;
+use crate::layout::{letter, Layout, Style};
+use crate::prefs::Prefs;
+use crate::{session, shapewrap, store, telnet};
+use crate::transport::Transport;
+use crate::users::Player;
+use crate::view::GameView;
+
+// how long a key waits for its screen before it's typed anyway, so a test
+// whose screen never comes fails instead of hanging
+const GIVE_UP: Duration = Duration::from_secs(10);
+
+type Ready = Rc<dyn Fn(&Vt) -> bool>;
+
+/// Keys waiting to be read and everything written so far.  Clones share
+/// both, so a test keeps one while the session owns another.  Once hung
+/// up, reads come to an end of file after the last key; once the pipe is
+/// broken, writes fail.
+#[derive(Clone)]
+pub struct Mem {
+    // each key with the screen it waits for and when it stops waiting
+    keys: Rc<RefCell<VecDeque<(Ready, Instant, u8)>>>,
+    out: Rc<RefCell<Vec<u8>>>,
+    // how much had been written when each key was read
+    marks: Rc<RefCell<Vec<usize>>>,
+    hung_up: Rc<Cell<bool>>,
+    broken: Rc<Cell<bool>>,
+    // keys taken for the current `fill_buf`
+    read: Vec<u8>
+}
+
+impl Mem {
+    pub fn new() -> Mem {
+        Mem {
+            keys: Rc::new(RefCell::new(VecDeque::new())),
+            out: Rc::new(RefCell::new(Vec::new())),
+            marks: Rc::new(RefCell::new(Vec::new())),
+            hung_up: Rc::new(Cell::new(false)),
+            broken: Rc::new(Cell::new(false)),
+            read: Vec::new()
+        }
+    }
+
+    pub fn type_keys(&self, keys: &[u8]) {
+        let ready: Ready = Rc::new(|_| true);
+        let now = Instant::now();
+        self.keys.borrow_mut().extend(keys.iter().map(|&k| (ready.clone(), now, k)));
+    }
+
+    /// Keys that, once the ones typed before them are read, wait until the
+    /// screen passes `ready`.
+    pub fn type_when(&self, ready: impl Fn(&Vt) -> bool + 'static, keys: &[u8]) {
+        let ready: Ready = Rc::new(ready);
+        let give_up = Instant::now() + GIVE_UP;
+        self.keys.borrow_mut().extend(keys.iter().map(|&k| (ready.clone(), give_up, k)));
+    }
+
+    /// The screen as it is now.
+    pub fn screen(&self) -> Vt {
+        let mut vt = Vt::new();
+        vt.feed(&self.out.borrow());
+        vt
+    }
+
+    /// The screen as it was when the `i`th key typed was read.
+    pub fn screen_at_key(&self, i: usize) -> Vt {
+        let mut vt = Vt::new();
+        vt.feed(&self.out.borrow()[..self.marks.borrow()[i]]);
+        vt
+    }
+
+    pub fn hang_up(&self) {
+        self.hung_up.set(true);
+    }
+
+    pub fn break_pipe(&self) {
+        self.broken.set(true);
+    }
+
+    pub fn output(&self) -> Vec<u8> {
+        self.out.borrow().clone()
+    }
+}
+
+impl BufRead for Mem {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.read.is_empty() {
+            let now = Instant::now();
+            let mut screen = None;
+            loop {
+                let key = match self.keys.borrow().front() {
+                    Some((ready, give_up, k)) if *give_up <= now || ready(screen.get_or_insert_with(|| self.screen())) => *k,
+                    _ => break
+                };
+                self.keys.borrow_mut().pop_front();
+                self.read.push(key);
+                self.marks.borrow_mut().push(self.out.borrow().len());
+            }
+        }
+        if self.read.is_empty() && !(self.hung_up.get() && self.keys.borrow().is_empty()) {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        Ok(&self.read)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.read.drain(..amt);
+    }
+}
+
+impl Read for Mem {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let rest = self.fill_buf()?;
+            let n = rest.len().min(buf.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl Write for Mem {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.broken.get() {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        self.out.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Mem {}
+
+pub const COLS: usize = 80;
+pub const LINES: usize = 24;
+
+/// As much of a VT100 as the game uses: cursor positioning, clearing,
+/// carriage returns and line feeds.  Colors and modes are read and
+/// dropped; anything written off the edge is lost.
+pub struct Vt {
+    grid: Vec<Vec<char>>,
+    // 0-based
+    row: usize,
+    col: usize
+}
+
+impl Vt {
+    pub fn new() -> Vt {
+        Vt { grid: vec![vec![' '; COLS]; LINES], row: 0, col: 0 }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let text = String::from_utf8_lossy(bytes);
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => match chars.next() {
+                    Some('[') => {
+                        let mut params = String::new();
+                        while let Some(&p) = chars.peek() {
+                            if p.is_ascii_digit() || p == ';' || p == '?' {
+                                params.push(p);
+                                chars.next();
+                            } else {
+                                break;
+                            }
+                        }
+                        if let Some(command) = chars.next() {
+                            self.csi(&params, command);
+                        }
+                    },
+                    // ESC ( B and friends take one more
+                    Some('(') | Some(')') => {
+                        chars.next();
+                    },
+                    _ => {}
+                },
+                '\r' => self.col = 0,
+                '\n' => self.line_feed(),
+                '\x08' => self.col = self.col.saturating_sub(1),
+                c if c < ' ' => {},
+                c => {
+                    if self.row < LINES && self.col < COLS {
+                        self.grid[self.row][self.col] = c;
+                    }
+                    self.col += 1;
+                }
+            }
+        }
+    }
+
+    fn line_feed(&mut self) {
+        if self.row + 1 < LINES {
+            self.row += 1;
+        } else {
+            self.grid.remove(0);
+            self.grid.push(vec![' '; COLS]);
+        }
+    }
+
+    fn csi(&mut self, params: &str, command: char) {
+        let n: Vec<usize> = params.trim_start_matches('?').split(';').map(|p| p.parse().unwrap_or(0)).collect();
+        let arg = |i: usize| n.get(i).cloned().filter(|&v| v > 0).unwrap_or(1);
+        match command {
+            'H' | 'f' => {
+                self.row = arg(0) - 1;
+                self.col = arg(1) - 1;
+            },
+            'A' => self.row = self.row.saturating_sub(arg(0)),
+            'B' => self.row = (self.row + arg(0)).min(LINES - 1),
+            'C' => self.col += arg(0),
+            'D' => self.col = self.col.saturating_sub(arg(0)),
+            'J' if params == "2" => self.grid = vec![vec![' '; COLS]; LINES],
+            'J' => {
+                for r in self.row..LINES {
+                    let from = if r == self.row { self.col.min(COLS) } else { 0 };
+                    for c in self.grid[r][from..].iter_mut() {
+                        *c = ' ';
+                    }
+                }
+            },
+            'K' if self.row < LINES => {
+                for c in self.grid[self.row][self.col.min(COLS)..].iter_mut() {
+                    *c = ' ';
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// The character at a 1-based terminal position, as `pos` takes them.
+    pub fn at(&self, p: Point) -> char {
+        self.grid.get(p.y - 1).and_then(|line| line.get(p.x - 1)).cloned().unwrap_or(' ')
+    }
+
+    /// Line `y`, counting from 1.
+    pub fn line(&self, y: usize) -> String {
+        self.grid[y - 1].iter().collect()
+    }
+
+    pub fn contains(&self, text: &str) -> bool {
+        (1..=LINES).any(|y| self.line(y).contains(text))
+    }
+}
+
+/// Letters and one line per row, so every board cell is a single
+/// character that says which shape is in it.
+pub fn plain_prefs() -> Prefs {
+    Prefs {
+        colors: "none".to_string(),
+        layout: "compact".to_string(),
+        ghost: false,
+        animations: false,
+        ..Prefs::default()
+    }
+}
+
+/// A dumb 80x24 terminal, as far as this thread's sessions know: letters
+/// on the compact layout, as `letters_layout` describes.
+pub fn dumb_terminal() {
+    telnet::set_terminal_type("dumb");
+    telnet::set_window_size((COLS as u16, LINES as u16));
+}
+
+pub fn letters_layout() -> Layout {
+    Layout::new("compact", Style::Letters)
+}
+
+/// A board with `shape` settled at `at`.
+pub fn board_with(shape: Shape, o: Orientation, at: Point) -> Board {
+    let mut b = Board::new();
+    for (x, y) in shapewrap::cells(shape, o) {
+        b.0[at.y + y][at.x + x] = Some(shape);
+    }
+    b
+}
+
+/// A view's game played through `events`, and the screen it leaves.
+pub fn render(prefs: &Prefs, events: &[Output]) -> (Layout, Vt) {
+    let mut s = Mem::new();
+    let mut view = GameView::new("test", prefs);
+    for evt in events {
+        view.handle(&mut s, evt);
+    }
+    let mut vt = Vt::new();
+    vt.feed(&s.output());
+    (view.layout, vt)
+}
+
+/// The board as drawn: `(x, y)` of every cell showing `c`.
+pub fn cells_showing(l: &Layout, vt: &Vt, c: char) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    for y in 0..tetrix::HEIGHT.min(crate::layout::ROWS) {
+        for x in 0..tetrix::WIDTH {
+            if vt.at(l.cell_pos(x, y)) == c {
+                out.push((x, y));
+            }
+        }
+    }
+    out
+}
+
+/// The board cells with a piece in them, whatever the piece.
+pub fn blocks_showing(l: &Layout, vt: &Vt) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    for y in 0..tetrix::HEIGHT.min(crate::layout::ROWS) {
+        for x in 0..tetrix::WIDTH {
+            let c = vt.at(l.cell_pos(x, y));
+            if c != letter(grid::Cell::Empty) && c != letter(grid::Cell::Ghost(Shape::Eye)) {
+                out.push((x, y));
+            }
+        }
+    }
+    out
+}
+
+/// A game on screen with a piece falling in it.
+pub fn piece_in_play(vt: &Vt) -> bool {
+    vt.contains("Next shape") && blocks_showing(&letters_layout(), vt).len() == 4
+}
+
+pub fn guest() -> Player {
+    Player { name: "test".to_string(), registered: false }
+}
+
+static SCRATCH_DIRS: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory of this thread's own for the user store and the replays,
+/// removed when it's dropped.
+pub struct Scratch(PathBuf);
+
+impl Scratch {
+    pub fn new() -> Scratch {
+        let n = SCRATCH_DIRS.fetch_add(1, Ordering::SeqCst);
+        let dir = env::temp_dir().join(format!("tetrix-telnet-test-{}-{}", process::id(), n));
+        fs::create_dir_all(&dir).unwrap();
+        store::set_root(&dir);
+        Scratch(dir)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        store::set_root(&PathBuf::new());
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A whole session on this thread, typed into through `mem`.
+pub fn run_session(mem: &Mem, player: Option<Player>) {
+    let _store = Scratch::new();
+    session::run(Box::new(mem.clone()), player);
+}
+
+fn moved(shape: Shape, from: Point, to: Point) -> Output {
+    Output::ShapePosition(shape, Some(Orientation::Up), Orientation::Up, Some(from), to)
+}
+
+fn tee_cells(at: Point) -> Vec<(usize, usize)> {
+    let mut cells: Vec<(usize, usize)> = shapewrap::cells(Shape::Tee, Orientation::Up).iter()
+        .map(|&(x, y)| (at.x + x, at.y + y))
+        .collect();
+    cells.sort();
+    cells
+}
+
+#[test]
+fn a_tee_moved_left_twice_and_dropped_lands_in_the_corner() {
+    let spawn = Point::new(4, 20);
+    let floor = Point::new(2, 0);
+    let (l, vt) = render(&plain_prefs(), &[
+        Output::GameStarted,
+        Output::ShapePosition(Shape::Tee, None, Orientation::Up, None, spawn),
+        // Left, Left
+        moved(Shape::Tee, spawn, Point::new(3, 20)),
+        moved(Shape::Tee, Point::new(3, 20), Point::new(2, 20)),
+        // Drop
+        moved(Shape::Tee, Point::new(2, 20), floor),
+        Output::ShapeLocked(Shape::Tee, board_with(Shape::Tee, Orientation::Up, floor))
+    ]);
+    let mut drawn = cells_showing(&l, &vt, '+');
+    drawn.sort();
+    assert_eq!(drawn, tee_cells(floor));
+}
+
+#[test]
+fn a_moving_piece_leaves_nothing_behind() {
+    let spawn = Point::new(4, 10);
+    let (l, vt) = render(&plain_prefs(), &[
+        Output::GameStarted,
+        Output::ShapePosition(Shape::Tee, None, Orientation::Up, None, spawn),
+        moved(Shape::Tee, spawn, Point::new(5, 9))
+    ]);
+    let mut drawn = cells_showing(&l, &vt, '+');
+    drawn.sort();
+    assert_eq!(drawn, tee_cells(Point::new(5, 9)));
+}
+
+#[test]
+fn a_completed_line_is_erased() {
+    let mut full = Board::new();
+    for x in 0..tetrix::WIDTH {
+        full.0[0][x] = Some(Shape::Eye);
+    }
+    full.0[1][0] = Some(Shape::Square);
+    let mut after = Board::new();
+    after.0[0][0] = Some(Shape::Square);
+    let (l, vt) = render(&plain_prefs(), &[
+        Output::GameStarted,
+        Output::ShapeLocked(Shape::Eye, full),
+        Output::LineCompleted(1, after),
+        Output::ScoreUpdate(1)
+    ]);
+    assert!(cells_showing(&l, &vt, '#').is_empty());
+    assert_eq!(cells_showing(&l, &vt, 'O'), vec![(0, 0)]);
+    assert!(vt.contains("Lines: 1"));
+}
+
+#[test]
+fn the_next_shape_is_shown_in_the_panel() {
+    let (_, vt) = render(&plain_prefs(), &[
+        Output::GameStarted,
+        Output::NextShape(Shape::Square)
+    ]);
+    assert!(vt.contains("Next shape"));
+}
+
+#[test]
+fn a_session_gets_from_the_lobby_to_the_title() {
+    let mem = Mem::new();
+    mem.type_keys(b"yq");
+    run_session(&mem, Some(guest()));
+    let vt = mem.screen();
+    assert!(vt.contains("Press \"s\" to start"));
+}
+
+#[test]
+fn keys_typed_in_a_game_move_and_drop_the_piece() {
+    dumb_terminal();
+    let mem = Mem::new();
+    mem.type_keys(b"ys");
+    mem.type_when(piece_in_play, b"jjk");
+    // once the piece is down; the next one is nowhere near the floor yet
+    mem.type_when(|vt| blocks_showing(&letters_layout(), vt).iter().filter(|c| c.1 < 4).count() == 4, b"q");
+    run_session(&mem, Some(guest()));
+    let l = letters_layout();
+    // keys 2 and 5 are the first "j" and the "q"
+    let before = blocks_showing(&l, &mem.screen_at_key(2));
+    assert_eq!(before.len(), 4, "no piece in play before the moves");
+    let bottom = before.iter().map(|c| c.1).min().unwrap();
+    let mut want: Vec<(usize, usize)> = before.iter().map(|&(x, y)| (x - 2, y - bottom)).collect();
+    // the next piece comes in at the top
+    let mut landed: Vec<(usize, usize)> = blocks_showing(&l, &mem.screen_at_key(5)).into_iter()
+        .filter(|c| c.1 < 4)
+        .collect();
+    want.sort();
+    landed.sort();
+    assert_eq!(landed, want);
+}
+
+#[test]
+fn a_held_piece_shows_in_the_panel() {
+    dumb_terminal();
+    let mem = Mem::new();
+    mem.type_keys(b"ys");
+    mem.type_when(piece_in_play, b"u");
+    mem.type_when(|vt| vt.contains("Held Shape"), b"q");
+    run_session(&mem, Some(guest()));
+    assert!(mem.screen_at_key(2).contains("Next shape"));
+    assert!(!mem.screen_at_key(2).contains("Held Shape"));
+    assert!(mem.screen_at_key(3).contains("Held Shape"));
+}
+
+#[test]
+fn a_hang_up_ends_the_session() {
+    let mem = Mem::new();
+    mem.type_keys(b"y");
+    mem.hang_up();
+    run_session(&mem, Some(guest()));
+    let vt = mem.screen();
+    assert!(vt.contains("Press \"s\" to start"));
+}
+
+#[test]
+fn a_hang_up_mid_game_puts_the_terminal_back() {
+    let mem = Mem::new();
+    mem.type_keys(b"ys");
+    mem.hang_up();
+    run_session(&mem, Some(guest()));
+    assert!(mem.output().ends_with(b"\x1b[?25h\x1b[0m\x1b[?1049l"));
+}
+
+#[test]
+fn writes_to_a_client_that_has_gone_end_the_session() {
+    let mem = Mem::new();
+    mem.type_keys(b"ys");
+    mem.break_pipe();
+    run_session(&mem, Some(guest()));
+    assert!(mem.output().is_empty());
+}
+
+#[test]
+fn a_hang_up_at_the_name_prompt_ends_the_session() {
+    let mem = Mem::new();
+    mem.type_keys(b"som");
+    mem.hang_up();
+    run_session(&mem, None);
+    let vt = mem.screen();
+    assert!(vt.contains("Name please?"));
+}
+
+#[test]
+fn the_emulator_follows_the_cursor() {
+    let mut vt = Vt::new();
+    vt.feed(b"\x1b[2J\x1b[3;5Hab\r\ncd\x1b[1;1Hx\x1b[K");
+    assert_eq!(vt.at(Point::new(5, 3)), 'a');
+    assert_eq!(vt.at(Point::new(6, 3)), 'b');
+    assert_eq!(vt.line(4).trim_end(), "cd");
+    assert_eq!(vt.line(1).trim_end(), "x");
+}
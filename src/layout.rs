@@ -0,0 +1,252 @@
+use tetrix::shape::{Shape, Point};
+use crate::grid::Cell;
+
+/// Board rows that are shown; anything a piece has above this is off screen.
+pub const ROWS: usize = 24;
+
+/// What a board cell is drawn with.  `Blocks` and `HalfBlocks` need a UTF-8
+/// client.  `Letters` gives every shape its own character so they can be
+/// told apart without color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Stars,
+    Blocks,
+    Brackets,
+    HalfBlocks,
+    Letters
+}
+
+pub const STYLES: &[&str] = &["stars", "blocks", "brackets", "halfblocks", "letters"];
+
+impl Style {
+    pub fn from_name(name: &str) -> Style {
+        match name {
+            "blocks" => Style::Blocks,
+            "brackets" => Style::Brackets,
+            "halfblocks" => Style::HalfBlocks,
+            "letters" => Style::Letters,
+            _ => Style::Stars
+        }
+    }
+}
+
+/// Where everything goes on screen.  Board cells are `cell_w` columns by
+/// `cell_h` lines, except in half-block mode where two board rows share a
+/// line.  All positions are 1-based terminal coordinates.
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    pub style: Style,
+    pub cell_w: usize,
+    pub cell_h: usize,
+    pub half: bool,
+    // line the top board row is on, and whether there are top/bottom edges
+    pub top: usize,
+    pub frame: bool,
+    pub panel_x: usize,
+    pub lines_row: usize,
+    pub level_row: usize,
+    // previews are bottom-anchored so taller shapes grow upwards
+    pub next_bottom: usize,
+    pub next_label_gap: usize,
+    pub held_bottom: usize,
+    pub held_label_row: usize,
+    pub status_row: usize
+}
+
+// the big layout wants 50 lines and its replay status runs to column 76
+const BIG_WINDOW: (u16, u16) = (76, 50);
+
+impl Layout {
+    /// `name` is "big" or "compact".  Half blocks bring their own geometry,
+    /// which fits 80x24 whatever the name.
+    pub fn new(name: &str, style: Style) -> Layout {
+        match style {
+            Style::HalfBlocks => Layout {
+                style,
+                cell_w: 2,
+                cell_h: 1,
+                half: true,
+                top: 2,
+                frame: true,
+                panel_x: 25,
+                lines_row: 8,
+                level_row: 9,
+                next_bottom: 6,
+                next_label_gap: 1,
+                held_bottom: 12,
+                held_label_row: 13,
+                status_row: 16
+            },
+            // one line per row leaves no room for the top and bottom edges
+            _ if name == "compact" => Layout {
+                style,
+                cell_w: 2,
+                cell_h: 1,
+                half: false,
+                top: 1,
+                frame: false,
+                panel_x: 25,
+                lines_row: 8,
+                level_row: 9,
+                next_bottom: 6,
+                next_label_gap: 1,
+                held_bottom: 15,
+                held_label_row: 16,
+                status_row: 17
+            },
+            _ => Layout {
+                style,
+                cell_w: 4,
+                cell_h: 2,
+                half: false,
+                top: 2,
+                frame: true,
+                panel_x: 46,
+                lines_row: 13,
+                level_row: 15,
+                next_bottom: 12,
+                next_label_gap: 2,
+                held_bottom: 26,
+                held_label_row: 27,
+                status_row: 30
+            }
+        }
+    }
+
+    /// Resolves the layout preference; "auto" goes big only when the client
+    /// has told us (via NAWS) that its window is large enough.
+    pub fn pick(pref: &str, style: Style, window: Option<(u16, u16)>) -> Layout {
+        let name = match (pref, window) {
+            ("auto", Some((w, h))) if w < BIG_WINDOW.0 || h < BIG_WINDOW.1 => "compact",
+            ("auto", _) => "big",
+            (name, _) => name
+        };
+        Layout::new(name, style)
+    }
+
+    /// Inside of the border, in columns and lines.
+    pub fn board_size(&self) -> (usize, usize) {
+        let lines = if self.half { ROWS / 2 } else { ROWS * self.cell_h };
+        (tetrix::WIDTH * self.cell_w, lines)
+    }
+
+    /// Top-left of board cell (x, y), y counting up from the bottom.  In
+    /// half-block mode rows 2n and 2n+1 come back with the same position.
+    pub fn cell_pos(&self, x: usize, y: usize) -> Point {
+        let line = if self.half {
+            self.top + (ROWS - 1 - y) / 2
+        } else {
+            self.top + (ROWS - 1 - y) * self.cell_h
+        };
+        Point::new(2 + x * self.cell_w, line)
+    }
+
+    /// The lines to paint for board cell `c`: the cell text, lettered if need
+    /// be, or blanks the same width.
+    pub fn cell_lines(&self, c: Cell) -> Vec<String> {
+        self.cell_text().iter().map(|t| match c {
+            Cell::Empty => " ".repeat(t.chars().count()),
+            _ if self.style == Style::Letters => t.replace('*', &letter(c).to_string()),
+            _ => t.to_string()
+        }).collect()
+    }
+
+    /// The text of one filled cell, a line per terminal line.  Cells are
+    /// spaced `cell_w` apart, so a shorter line leaves a gap.  Not used in
+    /// half-block mode, which draws rows in pairs.
+    pub fn cell_text(&self) -> &'static [&'static str] {
+        match (self.style, self.cell_h) {
+            (Style::HalfBlocks, _) => &["\u{2580}\u{2580}"],
+            (Style::Stars, 1) | (Style::Letters, 1) => &["*"],
+            (Style::Stars, _) | (Style::Letters, _) => &["* *", "* *"],
+            (Style::Blocks, 1) => &["\u{2588}\u{2588}"],
+            (Style::Blocks, _) => &["\u{2588}\u{2588}\u{2588}\u{2588}", "\u{2588}\u{2588}\u{2588}\u{2588}"],
+            (Style::Brackets, 1) => &["[]"],
+            (Style::Brackets, _) => &["[][]", "[][]"]
+        }
+    }
+}
+
+/// The character standing in for `*` in the `Letters` style.
+pub fn letter(c: Cell) -> char {
+    match c {
+        Cell::Empty => ' ',
+        Cell::Ghost(_) => '.',
+        Cell::Block(Shape::Eye) => '#',
+        Cell::Block(Shape::El) => '@',
+        Cell::Block(Shape::ElInv) => '%',
+        Cell::Block(Shape::Zee) => '$',
+        Cell::Block(Shape::ZeeInv) => '&',
+        Cell::Block(Shape::Square) => 'O',
+        Cell::Block(Shape::Tee) => '+'
+    }
+}
+
+pub const UPPER_HALF: char = '\u{2580}';
+pub const LOWER_HALF: char = '\u{2584}';
+pub const FULL_BLOCK: char = '\u{2588}';
+
+/// A lone shape drawn in a layout's style, one entry per terminal column of
+/// each line; `None` is a column the cursor steps over.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glyph {
+    pub lines: Vec<Vec<Option<char>>>
+}
+
+impl Glyph {
+    /// `cells` are (x, y) offsets from the shape's bottom-left corner.
+    pub fn new(cells: &[(usize, usize)], layout: &Layout) -> Glyph {
+        let w = cells.iter().map(|c| c.0 + 1).max().unwrap_or(0);
+        let h = cells.iter().map(|c| c.1 + 1).max().unwrap_or(0);
+        let filled = |x: usize, y: usize| cells.contains(&(x, y));
+        let mut lines = Vec::new();
+        if layout.half {
+            // pair rows from the top; an odd height leaves the bottom half empty
+            let mut top = h as isize - 1;
+            while top >= 0 {
+                let mut line = Vec::new();
+                for x in 0..w {
+                    let upper = filled(x, top as usize);
+                    let lower = top > 0 && filled(x, top as usize - 1);
+                    let c = match (upper, lower) {
+                        (true, true) => Some(FULL_BLOCK),
+                        (true, false) => Some(UPPER_HALF),
+                        (false, true) => Some(LOWER_HALF),
+                        (false, false) => None
+                    };
+                    for _ in 0..layout.cell_w {
+                        line.push(c);
+                    }
+                }
+                lines.push(line);
+                top -= 2;
+            }
+        } else {
+            let text = layout.cell_text();
+            for row in (0..h).rev() {
+                for t in text {
+                    let mut line = Vec::new();
+                    for x in 0..w {
+                        let chars: Vec<char> = t.chars().collect();
+                        for i in 0..layout.cell_w {
+                            let c = if x == w - 1 && i >= chars.len() {
+                                break;
+                            } else if filled(x, row) {
+                                chars.get(i).cloned().filter(|c| *c != ' ')
+                            } else {
+                                None
+                            };
+                            line.push(c);
+                        }
+                    }
+                    lines.push(line);
+                }
+            }
+        }
+        Glyph { lines }
+    }
+
+    pub fn height(&self) -> usize {
+        self.lines.len()
+    }
+}
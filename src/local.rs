@@ -0,0 +1,110 @@
+use std::env;
+use std::io::{self, BufRead, BufReader, Read, Stdout, Write};
+use std::mem;
+use crate::telnet;
+use crate::transport::Transport;
+use crate::users::{self, Player};
+
+// stdin, with reads that come back empty-handed rather than wait
+struct Keyboard;
+
+impl Read for Keyboard {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // there's no NAWS here, so look at the window whenever we're asked for keys
+        watch_window();
+        let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        match n {
+            n if n < 0 => Err(io::Error::last_os_error()),
+            // raw mode with VMIN 0: nothing typed yet, not the end of input
+            0 => Err(io::ErrorKind::WouldBlock.into()),
+            n => Ok(n as usize)
+        }
+    }
+}
+
+fn watch_window() {
+    let mut ws: libc::winsize = unsafe { mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } == 0 && ws.ws_col > 0 {
+        telnet::set_window_size((ws.ws_col, ws.ws_row));
+    }
+}
+
+/// The terminal the server was started from, in raw mode, so a session
+/// can be played without a telnet client: `--local`.  The terminal is put
+/// back the way it was when this is dropped.
+pub struct Local {
+    keys: BufReader<Keyboard>,
+    out: Stdout,
+    saved: libc::termios
+}
+
+impl Local {
+    /// Fails if stdin isn't a terminal.
+    pub fn new() -> io::Result<Local> {
+        let mut saved: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = saved;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        // reads return straight away, with or without a key
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if let Ok(term) = env::var("TERM") {
+            telnet::set_terminal_type(&term);
+        }
+        watch_window();
+        Ok(Local { keys: BufReader::new(Keyboard), out: io::stdout(), saved })
+    }
+}
+
+/// Whoever is at the terminal, as a guest.  A registered name needs its
+/// password, the same as over telnet, so the guest is "local" instead.
+pub fn player() -> Player {
+    let name = match env::var("USER") {
+        Ok(user) if !user.is_empty() && !users::is_registered(&user) => user,
+        _ => "local".to_string()
+    };
+    Player { name, registered: false }
+}
+
+impl Read for Local {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.keys.read(buf)
+    }
+}
+
+impl BufRead for Local {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.keys.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.keys.consume(amt)
+    }
+}
+
+impl Write for Local {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl Transport for Local {}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        // the terminal's modes matter more than the last colors, and a
+        // panic in here would skip them
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved) };
+        let _ = self.out.write(b"\x1b[0m\r\n");
+        let _ = self.out.flush();
+    }
+}
@@ -0,0 +1,119 @@
+use tetrix::GameWrapper;
+use tetrix::event::{Input, Output};
+use crate::prefs::Prefs;
+use crate::users::Player;
+use crate::{replay, stats, view};
+
+/// One game and the bookkeeping every frontend wants done for it: the
+/// replay, the player's stats, and the level going up with the lines.
+pub struct Game {
+    g: GameWrapper,
+    // the library's event queue, whatever its type
+    events: Box<dyn Fn() -> Vec<Output>>,
+    player: Player,
+    mode: String,
+    start_level: u8,
+    pub lvl: u8,
+    pub score: u32,
+    record: bool,
+    recorder: Option<replay::Recorder>,
+    tally: Option<stats::Tally>,
+    started: bool,
+    pub over: bool
+}
+
+impl Game {
+    pub fn new(p: &Player, prefs: &Prefs) -> Game {
+        let g = GameWrapper::new(tetrix::game());
+        let q = g.queue();
+        Game {
+            g,
+            events: Box::new(move || GameWrapper::drain(q.clone()).into_iter().collect()),
+            player: p.clone(),
+            mode: prefs.mode.clone(),
+            start_level: prefs.start_level,
+            lvl: prefs.start_level,
+            score: 0,
+            record: true,
+            recorder: None,
+            tally: None,
+            started: false,
+            over: false
+        }
+    }
+
+    /// A game nobody will want to watch again; no replay is saved
+    /// and it doesn't count in anyone's stats.
+    pub fn unrecorded(p: &Player, prefs: &Prefs) -> Game {
+        Game { record: false, ..Game::new(p, prefs) }
+    }
+
+    pub fn start(&mut self) {
+        self.g.set_level(self.lvl);
+        self.g.send(Input::StartGame);
+        self.started = true;
+    }
+
+    /// Started and not over yet.
+    pub fn running(&self) -> bool {
+        self.started && !self.over
+    }
+
+    pub fn send(&self, input: Input) {
+        self.g.send(input);
+    }
+
+    /// Whatever the game has said since the last call, once the replay,
+    /// the stats and the level have seen it.
+    pub fn poll(&mut self) -> Vec<Output> {
+        let events = (self.events)();
+        for evt in events.iter() {
+            if let (Output::GameStarted, true) = (evt, self.record) {
+                self.recorder = Some(replay::Recorder::new(&self.player, &self.mode, self.lvl));
+                self.tally = Some(stats::Tally::new());
+            }
+            if let Some(r) = self.recorder.as_mut() {
+                r.record(evt);
+            }
+            if let Some(t) = self.tally.as_mut() {
+                t.record(evt);
+            }
+            match evt {
+                Output::GameStarted => self.over = false,
+                Output::GameOver => {
+                    log::info!("[{}] game over!", self.player.name);
+                    self.over = true;
+                    self.finish();
+                },
+                Output::ScoreUpdate(score) => {
+                    self.score = *score;
+                    if view::level(*score, self.start_level) != self.lvl {
+                        self.lvl = view::level(*score, self.start_level);
+                        log::debug!("setting level to {}", self.lvl);
+                        self.g.set_level(self.lvl);
+                    }
+                },
+                _ => {}
+            }
+        }
+        events
+    }
+
+    /// Ends the game where it stands.
+    pub fn quit(&mut self) {
+        self.g.send(Input::EndGame);
+        self.over = true;
+        self.finish();
+    }
+
+    // files the replay and the stats; a second call does nothing
+    fn finish(&mut self) {
+        if let Some(r) = self.recorder.take() {
+            match r.finish() {
+                Ok(path) => log::info!("[{}] replay saved to {:?}", self.player.name, path),
+                Err(e) => log::error!("[{}] could not save replay: {}", self.player.name, e)
+            }
+        }
+        stats::record(&self.player, &self.mode, self.tally.take());
+    }
+}
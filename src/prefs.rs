@@ -0,0 +1,170 @@
+use json::JsonValue;
+use tetrix::event::Input;
+use crate::users::{self, Player};
+use crate::layout::{self, Layout, Style};
+use crate::telnet;
+use crate::theme::{self, Depth, Palette};
+
+pub const COLORS: &[&str] = &["16", "256", "truecolor", "none"];
+pub const LAYOUTS: &[&str] = &["auto", "big", "compact"];
+pub const MODES: &[&str] = &["marathon"];
+pub const MAX_START_LEVEL: u8 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keymap {
+    Classic,
+    Wasd
+}
+
+pub const KEYMAPS: &[Keymap] = &[Keymap::Classic, Keymap::Wasd];
+
+impl Keymap {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Keymap::Classic => "classic",
+            Keymap::Wasd => "wasd"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Keymap> {
+        KEYMAPS.iter().cloned().find(|k| k.name() == name)
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Keymap::Classic => "j/l move, k drop, x/z rotate, u hold",
+            Keymap::Wasd => "a/d move, s drop, w/e rotate, f hold"
+        }
+    }
+
+    /// The game input bound to a key, if any.  Arrow keys are handled
+    /// separately and work with every keymap.
+    pub fn input(&self, c: u8) -> Option<Input> {
+        match self {
+            Keymap::Classic => match c {
+                b'j' => Some(Input::Left),
+                b'l' => Some(Input::Right),
+                b'k' => Some(Input::Drop),
+                b'u' => Some(Input::Hold),
+                b'z' => Some(Input::Ccw),
+                b'x' => Some(Input::Cw),
+                _ => None
+            },
+            Keymap::Wasd => match c {
+                b'a' => Some(Input::Left),
+                b'd' => Some(Input::Right),
+                b's' => Some(Input::Drop),
+                b'f' => Some(Input::Hold),
+                b'e' => Some(Input::Ccw),
+                b'w' => Some(Input::Cw),
+                _ => None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Prefs {
+    pub keymap: Keymap,
+    pub theme: String,
+    pub colors: String,
+    pub ghost: bool,
+    pub layout: String,
+    pub glyphs: String,
+    pub start_level: u8,
+    pub mode: String,
+    pub accessible: bool,
+    pub animations: bool
+}
+
+impl Default for Prefs {
+    fn default() -> Prefs {
+        Prefs {
+            keymap: Keymap::Classic,
+            theme: theme::CLASSIC.name.to_string(),
+            colors: COLORS[0].to_string(),
+            ghost: true,
+            layout: LAYOUTS[0].to_string(),
+            glyphs: layout::STYLES[0].to_string(),
+            start_level: 0,
+            mode: MODES[0].to_string(),
+            accessible: false,
+            animations: true
+        }
+    }
+}
+
+// anything missing or no longer offered falls back to the default.
+fn pick(v: &JsonValue, options: &[&str], default: &str) -> String {
+    match v.as_str() {
+        Some(s) if options.iter().any(|o| *o == s) => s.to_string(),
+        _ => default.to_string()
+    }
+}
+
+impl Prefs {
+    /// The colors setting as the client can take it; a terminal that says
+    /// it's dumb gets none whatever was chosen.
+    pub fn colors(&self) -> &str {
+        if telnet::is_dumb() { "none" } else { self.colors.as_str() }
+    }
+
+    pub fn palette(&self) -> Palette {
+        Palette::new(theme::theme(&self.theme), Depth::from_name(self.colors()))
+    }
+
+    /// Without colors the shapes are only told apart by their letters.
+    pub fn layout(&self) -> Layout {
+        let style = if self.colors() == "none" { Style::Letters } else { Style::from_name(&self.glyphs) };
+        Layout::pick(&self.layout, style, telnet::window_size())
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        json::object!{
+            "keymap": self.keymap.name(),
+            "theme": self.theme.as_str(),
+            "colors": self.colors.as_str(),
+            "ghost": self.ghost,
+            "layout": self.layout.as_str(),
+            "glyphs": self.glyphs.as_str(),
+            "start_level": self.start_level,
+            "mode": self.mode.as_str(),
+            "accessible": self.accessible,
+            "animations": self.animations
+        }
+    }
+
+    pub fn from_json(v: &JsonValue) -> Prefs {
+        let d = Prefs::default();
+        Prefs {
+            keymap: v["keymap"].as_str().and_then(Keymap::from_name).unwrap_or(d.keymap),
+            theme: pick(&v["theme"], &theme::names(), &d.theme),
+            colors: pick(&v["colors"], COLORS, &d.colors),
+            ghost: v["ghost"].as_bool().unwrap_or(d.ghost),
+            layout: pick(&v["layout"], LAYOUTS, &d.layout),
+            glyphs: pick(&v["glyphs"], layout::STYLES, &d.glyphs),
+            start_level: v["start_level"].as_u8().unwrap_or(d.start_level).min(MAX_START_LEVEL),
+            mode: pick(&v["mode"], MODES, &d.mode),
+            accessible: v["accessible"].as_bool().unwrap_or(d.accessible),
+            animations: v["animations"].as_bool().unwrap_or(d.animations)
+        }
+    }
+}
+
+/// Guests always start from the defaults.
+pub fn load(p: &Player) -> Prefs {
+    if !p.registered {
+        return Prefs::default();
+    }
+    Prefs::from_json(&users::get(&p.name, "prefs"))
+}
+
+pub fn save(p: &Player, prefs: &Prefs) {
+    if !p.registered {
+        return;
+    }
+    match users::set(&p.name, "prefs", prefs.to_json()) {
+        Ok(_) => log::info!("[{}] saved preferences", p.name),
+        Err(e) => log::error!("[{}] could not save preferences: {}", p.name, e)
+    }
+}
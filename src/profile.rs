@@ -0,0 +1,100 @@
+use tetrix::shape::Point;
+use crate::codec;
+use crate::draw::*;
+use crate::stats;
+use crate::transport::Transport;
+
+fn hhmmss(ms: u64) -> String {
+    let secs = ms / 1000;
+    format!("{:02}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+fn draw_profile(s: &mut dyn Transport, name: &str, accessible: bool) {
+    if !accessible {
+        cls(s);
+        pos(s, Point::new(1,1));
+    }
+    s.write(format!("Profile: {}\r\n\r\n", name).as_bytes()).unwrap();
+    let st = match stats::load(name) {
+        Some(st) => st,
+        None => {
+            s.write(b"Nobody is registered by that name, and guests don't keep stats.\r\n").unwrap();
+            return;
+        }
+    };
+    s.write(format!("Games played:   {}\r\n", st.games).as_bytes()).unwrap();
+    s.write(format!("Total lines:    {}\r\n", st.lines).as_bytes()).unwrap();
+    s.write(format!("Tetrises:       {}\r\n", st.tetrises).as_bytes()).unwrap();
+    s.write(format!("Play time:      {}\r\n", hhmmss(st.play_ms)).as_bytes()).unwrap();
+    s.write(format!("Pieces/second:  {:.2}\r\n", st.pieces_per_second()).as_bytes()).unwrap();
+    s.write(b"\r\nBest lines by mode:\r\n").unwrap();
+    if st.best.is_empty() {
+        s.write(b"  (no games yet)\r\n").unwrap();
+    }
+    for (mode, best) in &st.best {
+        s.write(format!("  {:<14}{}\r\n", mode, best).as_bytes()).unwrap();
+    }
+    s.write(format!("\r\nPieces placed ({}):\r\n", st.total_pieces()).as_bytes()).unwrap();
+    for shape in codec::SHAPES.iter() {
+        let name = codec::shape_name(*shape);
+        s.write(format!("  {:<14}{}\r\n", name, st.pieces.get(name).cloned().unwrap_or(0)).as_bytes()).unwrap();
+    }
+}
+
+/// The Profile screen.  Asks whose stats to show, defaulting to the person
+/// asking, and shows them until a key is pressed.  For screen readers it
+/// just carries on down the page.
+pub struct Profile {
+    typed: Vec<u8>,
+    shown: bool,
+    accessible: bool
+}
+
+impl Profile {
+    pub fn new(accessible: bool) -> Profile {
+        Profile { typed: Vec::new(), shown: false, accessible }
+    }
+
+    pub fn draw(&self, s: &mut dyn Transport) {
+        if !self.accessible {
+            cls(s);
+            pos(s, Point::new(1,1));
+        }
+        s.write(b"Whose profile? [enter for your own] ").unwrap();
+        s.write(&self.typed).unwrap();
+        s.flush().unwrap();
+    }
+
+    /// Types the name, echoing it, then shows that profile.  True for the
+    /// key that leaves it.
+    pub fn key(&mut self, s: &mut dyn Transport, n: &str, c: u8) -> bool {
+        if self.shown {
+            // the other half of the enter that asked for it
+            return c != b'\n' && c != 0;
+        }
+        match c {
+            b'\r' | b'\n' => {
+                let who = String::from_utf8_lossy(&self.typed).trim().to_string();
+                let who = if who.is_empty() { n.to_string() } else { who };
+                log::info!("[{}] viewing the profile of {}", n, who);
+                s.write(b"\r\n").unwrap();
+                draw_profile(s, &who, self.accessible);
+                s.write(b"\r\n[press any key to continue]\r\n").unwrap();
+                self.shown = true;
+            },
+            // backspace and delete
+            8 | 127 => {
+                if self.typed.pop().is_some() {
+                    s.write(b"\x08 \x08").unwrap();
+                }
+            },
+            c if c >= b' ' => {
+                self.typed.push(c);
+                s.write(&[c]).unwrap();
+            },
+            _ => {}
+        }
+        s.flush().unwrap();
+        false
+    }
+}
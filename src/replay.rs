@@ -0,0 +1,187 @@
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use json::JsonValue;
+use tetrix::event::Output;
+use crate::codec;
+use crate::store;
+use crate::users::Player;
+
+pub const REPLAY_DIR: &str = "replays";
+const REPLAY_EXT: &str = "replay";
+
+/// The header line of a replay file.  `duration` is in milliseconds,
+/// `started` in seconds since the epoch.
+#[derive(Debug, Clone)]
+pub struct ReplayMeta {
+    pub player: String,
+    pub guest: bool,
+    pub mode: String,
+    pub start_level: u8,
+    pub score: u32,
+    pub duration: u64,
+    pub version: String,
+    pub started: u64
+}
+
+impl ReplayMeta {
+    pub fn to_json(&self) -> JsonValue {
+        json::object!{
+            "player": self.player.as_str(),
+            "guest": self.guest,
+            "mode": self.mode.as_str(),
+            "start_level": self.start_level,
+            "score": self.score,
+            "duration": self.duration,
+            "version": self.version.as_str(),
+            "started": self.started
+        }
+    }
+
+    pub fn from_json(v: &JsonValue) -> Option<ReplayMeta> {
+        Some(ReplayMeta {
+            player: v["player"].as_str()?.to_string(),
+            guest: v["guest"].as_bool().unwrap_or(false),
+            mode: v["mode"].as_str()?.to_string(),
+            start_level: v["start_level"].as_u8().unwrap_or(0),
+            score: v["score"].as_u32()?,
+            duration: v["duration"].as_u64()?,
+            version: v["version"].as_str()?.to_string(),
+            started: v["started"].as_u64()?
+        })
+    }
+}
+
+/// Collects the output event stream of one game.  Each event is stored as a
+/// line of JSON with a `t` field holding milliseconds since the recorder was
+/// created; the whole thing is written out by `finish` once the final score
+/// and duration are known.
+pub struct Recorder {
+    meta: ReplayMeta,
+    start: Instant,
+    events: Vec<String>
+}
+
+impl Recorder {
+    pub fn new(player: &Player, mode: &str, start_level: u8) -> Recorder {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        Recorder {
+            meta: ReplayMeta {
+                player: player.name.clone(),
+                guest: !player.registered,
+                mode: mode.to_string(),
+                start_level,
+                score: 0,
+                duration: 0,
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                started
+            },
+            start: Instant::now(),
+            events: Vec::new()
+        }
+    }
+
+    pub fn record(&mut self, evt: &Output) {
+        if let Output::ScoreUpdate(score) = evt {
+            self.meta.score = *score;
+        }
+        if let Some(mut v) = codec::output_to_json(evt) {
+            v["t"] = (self.start.elapsed().as_millis() as u64).into();
+            self.events.push(v.dump());
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<PathBuf> {
+        self.meta.duration = self.start.elapsed().as_millis() as u64;
+        let dir = store::path(REPLAY_DIR);
+        fs::create_dir_all(&dir)?;
+        let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+        let path = dir
+            .join(format!("{}-{}.{}", stamp, file_safe(&self.meta.player), REPLAY_EXT));
+        let mut f = BufWriter::new(File::create(&path)?);
+        writeln!(f, "{}", self.meta.to_json().dump())?;
+        for e in &self.events {
+            writeln!(f, "{}", e)?;
+        }
+        f.flush()?;
+        Ok(path)
+    }
+}
+
+fn file_safe(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// A replay read back from disk, events paired with their timestamps.
+pub struct Replay {
+    pub meta: ReplayMeta,
+    pub events: Vec<(u64, Output)>
+}
+
+fn parse_meta(line: &str) -> Option<ReplayMeta> {
+    json::parse(line).ok().and_then(|v| ReplayMeta::from_json(&v))
+}
+
+pub fn load(path: &Path) -> io::Result<Replay> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let meta = match lines.next() {
+        Some(line) => parse_meta(&line?),
+        None => None
+    };
+    let meta = meta.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad replay header"))?;
+    let mut events = Vec::new();
+    for line in lines {
+        let v = match json::parse(&line?) {
+            Ok(v) => v,
+            Err(_) => continue
+        };
+        if let (Some(t), Some(evt)) = (v["t"].as_u64(), codec::output_from_json(&v)) {
+            events.push((t, evt));
+        }
+    }
+    Ok(Replay { meta, events })
+}
+
+fn read_meta(path: &Path) -> Option<ReplayMeta> {
+    let mut line = String::new();
+    BufReader::new(File::open(path).ok()?).read_line(&mut line).ok()?;
+    parse_meta(&line)
+}
+
+/// Every readable replay in `REPLAY_DIR`, unsorted.  Only the header line of
+/// each file is read.
+pub fn list() -> Vec<(PathBuf, ReplayMeta)> {
+    let mut out = Vec::new();
+    let entries = match fs::read_dir(store::path(REPLAY_DIR)) {
+        Ok(entries) => entries,
+        Err(_) => return out
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().map_or(false, |e| e == REPLAY_EXT) {
+            match read_meta(&path) {
+                Some(meta) => out.push((path, meta)),
+                None => log::warn!("skipping unreadable replay {:?}", path)
+            }
+        }
+    }
+    out
+}
+
+pub fn recent(limit: usize) -> Vec<(PathBuf, ReplayMeta)> {
+    let mut all = list();
+    all.sort_by(|a, b| b.1.started.cmp(&a.1.started));
+    all.truncate(limit);
+    all
+}
+
+pub fn top(limit: usize) -> Vec<(PathBuf, ReplayMeta)> {
+    let mut all = list();
+    all.sort_by(|a, b| b.1.score.cmp(&a.1.score).then(a.1.duration.cmp(&b.1.duration)));
+    all.truncate(limit);
+    all
+}
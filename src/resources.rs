@@ -15,6 +15,8 @@ Press \"s\" to start\r\n
  \"u\"  to stash the shape or recover the held shape\r
 \"j/l\" or arrow keys to move left and right\r
  \"k\"  or down arrow to drop the shape to the bottom\r
+ \"o\"  for settings: keys, ghost piece, starting level\r
+\"^L\"  to redraw the screen\r
  \"q\"  to quit at any time\r";
 
 pub static GAME_OVER_TEXT: &[u8] = b"_______  _______  _______  _______ \r
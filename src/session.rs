@@ -0,0 +1,629 @@
+use std::io::{self, prelude::*};
+use std::thread;
+use std::time::{Duration, Instant};
+use tetrix::shape::Point;
+use tetrix::event::Input;
+use crate::draw::*;
+use crate::users::{self, Player};
+use crate::prefs::{self, Prefs};
+use crate::{ai, play, profile, resources, settings, speech, telnet, view, watch};
+use crate::theme::Palette;
+use crate::transport::{self, Link, Transport};
+
+/// Where a connection is.  Each screen draws itself when it's entered and
+/// handles its own keys; `run` moves between them.  Connecting and Login
+/// ask their questions a line at a time, so they do their reading in
+/// `enter` and say where to go when they're done.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Screen {
+    Connecting,
+    Login,
+    Lobby,
+    Watch,
+    Replay,
+    Profile,
+    Title,
+    Demo,
+    Help,
+    Settings,
+    Playing,
+    GameOver,
+    Results,
+    Closed
+}
+
+/// One game from the title screen to the results, and how it looks.
+struct Game {
+    play: play::Game,
+    view: view::Display
+}
+
+impl Game {
+    fn new(p: &Player, prefs: &Prefs) -> Game {
+        Game { play: play::Game::new(p, prefs), view: view::Display::new(&p.name, prefs) }
+    }
+
+    /// Takes in whatever the game has said since the last pass.
+    fn pump(&mut self, s: &mut dyn Transport) {
+        for evt in self.play.poll() {
+            self.view.handle(s, &evt);
+        }
+        self.view.tick(s);
+    }
+}
+
+/// The title screen's attract mode: a game the computer plays, with a
+/// reminder over the board of how to play one yourself.
+struct Demo {
+    game: Game,
+    ai: ai::Ai,
+    pal: Palette
+}
+
+impl Demo {
+    fn new(prefs: &Prefs) -> Demo {
+        let p = Player { name: "CPU".to_string(), registered: false };
+        let mut game = Game { play: play::Game::unrecorded(&p, prefs), view: view::Display::new(&p.name, prefs) };
+        game.play.start();
+        Demo { game, ai: ai::Ai::new(ai::difficulty("normal").unwrap()), pal: prefs.palette() }
+    }
+
+    fn pump(&mut self, s: &mut dyn Transport) {
+        let events = self.game.play.poll();
+        for evt in events.iter() {
+            self.ai.handle(evt);
+            self.game.view.handle(s, evt);
+        }
+        if let Some(input) = self.ai.next_input() {
+            self.game.play.send(input);
+        }
+        self.game.view.tick(s);
+        // the board is drawn over it as pieces pass, so it goes back on after
+        if !events.is_empty() {
+            self.overlay(s);
+        }
+    }
+
+    fn overlay(&self, s: &mut dyn Transport) {
+        let l = match &self.game.view {
+            view::Display::Screen(v) => v.layout,
+            view::Display::Words(_) => return
+        };
+        let (w, h) = l.board_size();
+        pos(s, Point::new(2 + w.saturating_sub(DEMO_TEXT.len()) / 2, l.top + h / 2));
+        // reversed when there are colors to reverse, as the level flashes
+        sgr(s, &self.pal.text);
+        if !self.pal.reset.is_empty() {
+            sgr(s, "7m");
+        }
+        s.write(DEMO_TEXT.as_bytes()).unwrap();
+        sgr(s, &self.pal.text);
+        s.flush().unwrap();
+    }
+}
+
+// how long the title waits for a key before the demo starts
+const DEMO_AFTER: Duration = Duration::from_secs(10);
+const DEMO_TEXT: &str = "PRESS S TO START";
+
+struct Session {
+    s: Link,
+    player: Player,
+    prefs: Prefs,
+    game: Option<Game>,
+    demo: Option<Demo>,
+    menu: Option<watch::Menu>,
+    replay: Option<watch::Playback>,
+    profile: Option<profile::Profile>,
+    // the last key, or when the title came up
+    idle_since: Instant,
+    fullscreen: bool
+}
+
+/// Runs one connection through its screens until it's closed.  A
+/// `player` already known skips asking who's there.
+pub fn run(s: Box<dyn Transport>, player: Option<Player>) {
+    let mut session = Session {
+        s: Link::new(s),
+        player: Player { name: String::new(), registered: false },
+        prefs: Prefs::default(),
+        game: None,
+        demo: None,
+        menu: None,
+        replay: None,
+        profile: None,
+        idle_since: Instant::now(),
+        fullscreen: false
+    };
+    let mut screen = Screen::Connecting;
+    if let Some(player) = player {
+        session.prefs = prefs::load(&player);
+        session.player = player;
+        screen = Screen::Lobby;
+    }
+    while screen != Screen::Closed {
+        log::debug!("[{}] on to {:?}", session.player.name, screen);
+        let mut next = session.enter(screen);
+        while next.is_none() {
+            next = session.tick(screen);
+            if next.is_none() {
+                next = match session.poll_key(screen) {
+                    Ok(Some(c)) => session.key(screen, c),
+                    Ok(None) => None,
+                    Err(e) => {
+                        log::info!("[{}] connection lost: {}", session.player.name, e);
+                        Some(Screen::Closed)
+                    }
+                };
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        screen = next.unwrap();
+    }
+    session.close();
+    log::info!("{} disconnected", session.player.name);
+}
+
+impl Session {
+    /// However the session ended, a game still going is ended there so its
+    /// replay and stats are filed, and the terminal is put back.
+    fn close(&mut self) {
+        if let Some(game) = self.game.as_mut() {
+            if game.play.running() {
+                game.play.quit();
+            }
+        }
+        if let Some(mut demo) = self.demo.take() {
+            demo.game.play.quit();
+        }
+        if self.fullscreen {
+            leave_fullscreen(&mut self.s);
+        }
+    }
+
+    /// Draws a screen on the way in.  Some(next) if it's already done.
+    fn enter(&mut self, screen: Screen) -> Option<Screen> {
+        match screen {
+            Screen::Connecting => self.connecting(),
+            Screen::Login => self.login(),
+            Screen::Lobby => {
+                // replays are drawn with the cursor all over the screen, which
+                // a screen reader can't follow
+                let watch = if self.prefs.accessible { "" } else { "w to watch replays, " };
+                self.s.write(format!("{}, would you like to play a game? [y/N, {}p for profiles] ", self.player.name, watch).as_bytes()).unwrap();
+                self.s.flush().unwrap();
+                None
+            },
+            Screen::Watch => {
+                self.menu.get_or_insert_with(watch::Menu::new).draw(&mut self.s);
+                None
+            },
+            Screen::Replay => {
+                self.go_fullscreen();
+                cls(&mut self.s);
+                None
+            },
+            Screen::Profile => {
+                let accessible = self.prefs.accessible;
+                self.profile.get_or_insert_with(|| profile::Profile::new(accessible)).draw(&mut self.s);
+                None
+            },
+            Screen::Title => {
+                self.go_fullscreen();
+                if self.game.is_none() {
+                    self.game = Some(Game::new(&self.player, &self.prefs));
+                }
+                print_intro(&mut self.s, &self.prefs);
+                self.idle_since = Instant::now();
+                None
+            },
+            Screen::Demo => {
+                cls(&mut self.s);
+                self.demo = Some(Demo::new(&self.prefs));
+                None
+            },
+            Screen::Help => {
+                print_help(&mut self.s, &self.prefs);
+                None
+            },
+            Screen::Settings => {
+                settings::draw(&mut self.s, &self.player, &self.prefs);
+                None
+            },
+            Screen::Results => {
+                self.results();
+                None
+            },
+            Screen::Playing | Screen::GameOver | Screen::Closed => None
+        }
+    }
+
+    // screen readers and dumb terminals get no escapes they didn't ask for
+    fn go_fullscreen(&mut self) {
+        if !self.fullscreen && !self.prefs.accessible && !telnet::is_dumb() {
+            enter_fullscreen(&mut self.s);
+            self.fullscreen = true;
+        }
+    }
+
+    // back to the lobby's scrolling lines
+    fn to_lobby(&mut self) -> Option<Screen> {
+        if self.fullscreen {
+            leave_fullscreen(&mut self.s);
+            self.fullscreen = false;
+        }
+        if self.prefs.accessible {
+            self.s.write(b"\r\n").unwrap();
+        } else {
+            cls(&mut self.s);
+            pos(&mut self.s, Point::new(1,1));
+        }
+        Some(Screen::Lobby)
+    }
+
+    /// Called every pass while a screen waits for keys.
+    fn tick(&mut self, screen: Screen) -> Option<Screen> {
+        match screen {
+            Screen::Replay => {
+                self.replay.as_mut().unwrap().tick(&mut self.s);
+                return None;
+            },
+            // the demo needs the screen to itself, so only where the title has one
+            Screen::Title if self.fullscreen && !self.prefs.accessible && self.idle_since.elapsed() >= DEMO_AFTER => {
+                return Some(Screen::Demo);
+            },
+            Screen::Demo => {
+                let demo = self.demo.as_mut().unwrap();
+                demo.pump(&mut self.s);
+                if demo.game.play.over && !demo.game.view.animating() {
+                    self.demo = None;
+                    return Some(Screen::Title);
+                }
+                return None;
+            },
+            _ => {}
+        }
+        let game = match (screen, self.game.as_mut()) {
+            (Screen::Playing, Some(game)) | (Screen::GameOver, Some(game)) => game,
+            _ => return None
+        };
+        game.pump(&mut self.s);
+        match screen {
+            Screen::Playing if game.play.over => Some(Screen::GameOver),
+            // the top-out plays before the results
+            Screen::GameOver if !game.view.animating() => Some(Screen::Results),
+            _ => None
+        }
+    }
+
+    // a key if one has come in, an error once the client has gone; telnet
+    // commands are dealt with here, a resize by drawing the game again in a
+    // layout that fits
+    fn poll_key(&mut self, screen: Screen) -> io::Result<Option<u8>> {
+        let window = telnet::window_size();
+        let mut buf = [0; 1];
+        let key = match self.s.read_exact(&mut buf) {
+            Err(ref e) if !transport::gone(e) => None,
+            Err(e) => return Err(e),
+            Ok(()) if buf[0] == telnet::IAC => telnet::command(&mut self.s)?,
+            Ok(()) => Some(buf[0])
+        };
+        if telnet::window_size() != window {
+            if let (Screen::Playing, Some(game)) | (Screen::GameOver, Some(game)) = (screen, self.game.as_mut()) {
+                game.view.redraw(&mut self.s);
+            }
+            if let (Screen::Demo, Some(demo)) = (screen, self.demo.as_mut()) {
+                demo.game.view.redraw(&mut self.s);
+                demo.overlay(&mut self.s);
+            }
+            if let (Screen::Replay, Some(replay)) = (screen, self.replay.as_mut()) {
+                replay.redraw(&mut self.s);
+            }
+        }
+        Ok(key)
+    }
+
+    fn key(&mut self, screen: Screen, c: u8) -> Option<Screen> {
+        self.idle_since = Instant::now();
+        match screen {
+            Screen::Lobby => match c {
+                b'y' | b'Y' => Some(Screen::Title),
+                b'w' | b'W' if !self.prefs.accessible => Some(Screen::Watch),
+                b'p' | b'P' => Some(Screen::Profile),
+                b'n' | b'N' => {
+                    sgr(&mut self.s, &self.prefs.palette().reset);
+                    self.s.write(b"Bye!\r\n").unwrap();
+                    self.s.flush().unwrap();
+                    Some(Screen::Closed)
+                },
+                _ => None
+            },
+            Screen::Watch => match c {
+                b'q' => {
+                    self.menu = None;
+                    self.to_lobby()
+                },
+                12 => {
+                    self.menu.as_mut().unwrap().draw(&mut self.s);
+                    None
+                },
+                c => {
+                    let path = self.menu.as_mut().unwrap().key(&mut self.s, c)?;
+                    log::info!("[{}] watching replay {:?}", self.player.name, path);
+                    self.replay = Some(watch::Playback::load(path, &self.prefs)?);
+                    Some(Screen::Replay)
+                }
+            },
+            Screen::Replay => match c {
+                b'q' => {
+                    self.replay = None;
+                    if self.fullscreen {
+                        leave_fullscreen(&mut self.s);
+                        self.fullscreen = false;
+                    }
+                    Some(Screen::Watch)
+                },
+                12 => {
+                    self.replay.as_mut().unwrap().redraw(&mut self.s);
+                    None
+                },
+                c => {
+                    self.replay.as_mut().unwrap().key(&mut self.s, c);
+                    None
+                }
+            },
+            Screen::Profile => {
+                if self.profile.as_mut().unwrap().key(&mut self.s, &self.player.name, c) {
+                    self.profile = None;
+                    return self.to_lobby();
+                }
+                None
+            },
+            Screen::Settings => {
+                if settings::key(&self.player, &mut self.prefs, c) {
+                    // the display follows the new settings
+                    self.game = None;
+                    return Some(Screen::Title);
+                }
+                settings::draw(&mut self.s, &self.player, &self.prefs);
+                None
+            },
+            Screen::Title => self.title_key(c),
+            Screen::Help => Some(Screen::Title),
+            Screen::Demo => {
+                self.demo.take().unwrap().game.play.quit();
+                // as the overlay says; any other key is back to the title
+                match c {
+                    b's' => self.title_key(c),
+                    _ => Some(Screen::Title)
+                }
+            },
+            Screen::Playing => self.playing_key(c),
+            Screen::GameOver => match c {
+                b'q' => Some(Screen::Closed),
+                // ctrl-L
+                12 => {
+                    self.game.as_mut().unwrap().view.redraw(&mut self.s);
+                    None
+                },
+                _ => None
+            },
+            Screen::Results => match c {
+                b'y' => {
+                    log::info!("[{}] wants to play again", self.player.name);
+                    self.game = None;
+                    Some(Screen::Title)
+                },
+                b'n' => {
+                    log::info!("[{}] wants to quit", self.player.name);
+                    Some(Screen::Closed)
+                },
+                12 => {
+                    self.results();
+                    None
+                },
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    fn title_key(&mut self, c: u8) -> Option<Screen> {
+        match c {
+            b'h' => Some(Screen::Help),
+            b'o' => Some(Screen::Settings),
+            b's' => {
+                self.game.as_mut().unwrap().play.start();
+                Some(Screen::Playing)
+            },
+            b'q' => Some(Screen::Closed),
+            // ctrl-L
+            12 => {
+                print_intro(&mut self.s, &self.prefs);
+                None
+            },
+            _ => None
+        }
+    }
+
+    fn playing_key(&mut self, c: u8) -> Option<Screen> {
+        let game = self.game.as_mut().unwrap();
+        let s = &mut self.s;
+        match c {
+            // ctrl-L
+            12 => game.view.redraw(s),
+            b'r' => log::info!("report: {}", game.view.current_board().report()),
+            c if speech::QUERY_KEYS.contains(&c) => {
+                if let view::Display::Words(w) = &game.view {
+                    w.query(s, c);
+                }
+            },
+            b'q' => {
+                game.play.quit();
+                return Some(Screen::Closed);
+            },
+            27 => {
+                let mut buf = [0; 2];
+                if s.read_exact(&mut buf).is_ok() {
+                    match buf {
+                        [91, 68] => game.play.send(Input::Left),
+                        [91, 67] => game.play.send(Input::Right),
+                        [91, 66] => game.play.send(Input::Drop),
+                        _ => {}
+                    }
+                }
+            },
+            0 => {},
+            c => match self.prefs.keymap.input(c) {
+                Some(input) => game.play.send(input),
+                None => log::info!("unknown user input: {:?}", c)
+            }
+        }
+        None
+    }
+
+    fn connecting(&mut self) -> Option<Screen> {
+        let s = &mut self.s;
+        // the client answers while the name is being typed, so by the end
+        // of the line we know whether it's a dumb terminal
+        telnet::request_terminal_type(s);
+        s.write(b"Name please? ").unwrap();
+        s.flush().unwrap();
+        // a telnet client still in line mode echoes for itself
+        let echo = !s.telnet();
+        let buf = match telnet::read_line_raw(s, echo) {
+            Ok(buf) => buf,
+            Err(_) => return Some(Screen::Closed)
+        };
+        let name = buf.trim();
+        log::info!("Users name is {}", name);
+        if name == "" {
+            s.write(b"No name? Bye!\r\n").unwrap();
+            s.flush().unwrap();
+            return Some(Screen::Closed);
+        }
+        if !telnet::is_dumb() {
+            // black out the background before anything is drawn
+            sgr(s, "0;37;40m");
+            cls(s);
+            pos(s, Point::new(1,1));
+        }
+        self.player.name = name.to_string();
+        Some(Screen::Login)
+    }
+
+    fn login(&mut self) -> Option<Screen> {
+        self.player = match login(&mut self.s, &self.player.name) {
+            Some(player) => player,
+            None => {
+                self.s.flush().unwrap();
+                log::info!("{} failed to log in", self.player.name);
+                return Some(Screen::Closed);
+            }
+        };
+        self.prefs = prefs::load(&self.player);
+        log::info!("Forcing client to character mode; no echo");
+        telnet::character_mode(&mut self.s);
+        telnet::request_window_size(&mut self.s);
+        Some(Screen::Lobby)
+    }
+
+    fn results(&mut self) {
+        let s = &mut self.s;
+        let game = self.game.as_ref().unwrap();
+        if self.prefs.accessible {
+            s.write(format!("{} lines, level {}. Play again? [y/n]\r\n", game.play.score, game.play.lvl + 1).as_bytes()).unwrap();
+        } else {
+            cls(s);
+            pos(s, Point::new(1,1));
+            s.write(resources::GAME_OVER_TEXT).unwrap();
+            s.write(format!("\r\n        {} lines, level {}\r\n", game.play.score, game.play.lvl + 1).as_bytes()).unwrap();
+        }
+        s.flush().unwrap();
+    }
+}
+
+fn print_help(s: &mut dyn Transport, prefs: &Prefs) {
+    if !prefs.accessible {
+        cls(s);
+    }
+    s.write(b"'i' and 'j' to move shapes; 'z' and 'x' rotate\r\n").unwrap();
+    s.write(b"'k' to drop; 'q' will quit.  have fun!!!\r\n").unwrap();
+    s.write(b"'s' to start the game!\r\n").unwrap();
+    s.write(b"'o' for settings\r\n").unwrap();
+    s.write(b"[press any key to continue]\r\n").unwrap();
+    s.flush().unwrap();
+}
+
+fn print_title(s: &mut dyn Transport) {
+    cls(s);
+    pos(s, Point::new(1,1));
+    s.write(resources::TITLE_TEXT).unwrap();
+    s.flush().unwrap();
+}
+
+/// The title screen, or a line saying the same for screen readers.
+fn print_intro(s: &mut dyn Transport, prefs: &Prefs) {
+    if prefs.accessible {
+        s.write(b"Screen reader mode. Press s to start, h for help, o for settings, q to quit.\r\n").unwrap();
+        s.flush().unwrap();
+    } else {
+        print_title(s);
+    }
+}
+
+/// Password check for registered names; offers registration to new ones.
+/// `None` means the connection should be dropped.
+fn login(s: &mut dyn Transport, name: &str) -> Option<Player> {
+    let guest = Player { name: name.to_string(), registered: false };
+    telnet::hide_input(s);
+    if users::is_registered(name) {
+        for _ in 0..3 {
+            s.write(b"Password: ").unwrap();
+            s.flush().unwrap();
+            let password = telnet::read_line_raw(s, false).ok()?;
+            s.write(b"\r\n").unwrap();
+            if users::verify(name, &password) {
+                log::info!("{} logged in", name);
+                return Some(Player { name: name.to_string(), registered: true });
+            }
+            log::info!("{} failed a password check", name);
+            s.write(b"Wrong password.\r\n").unwrap();
+        }
+        return None;
+    }
+
+    s.write(format!("{} isn't registered. Choose a password to claim it,\r\nor just press enter to play as a guest: ", name).as_bytes()).unwrap();
+    s.flush().unwrap();
+    let password = telnet::read_line_raw(s, false).ok()?;
+    s.write(b"\r\n").unwrap();
+    if password.is_empty() {
+        log::info!("{} is playing as a guest", name);
+        s.write(b"Playing as a guest.\r\n").unwrap();
+        return Some(guest);
+    }
+    s.write(b"Once more to confirm: ").unwrap();
+    s.flush().unwrap();
+    let confirm = telnet::read_line_raw(s, false).ok()?;
+    s.write(b"\r\n").unwrap();
+    if confirm != password {
+        s.write(b"Passwords don't match, playing as a guest.\r\n").unwrap();
+        return Some(guest);
+    }
+    match users::register(name, &password) {
+        Ok(true) => {
+            log::info!("{} registered", name);
+            s.write(b"Registered!\r\n").unwrap();
+            Some(Player { name: name.to_string(), registered: true })
+        },
+        Ok(false) => {
+            s.write(b"Somebody just registered that name. Bye!\r\n").unwrap();
+            None
+        },
+        Err(e) => {
+            log::error!("could not register {}: {}", name, e);
+            s.write(b"Couldn't save your account, playing as a guest.\r\n").unwrap();
+            Some(guest)
+        }
+    }
+}
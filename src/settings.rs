@@ -0,0 +1,74 @@
+use tetrix::shape::Point;
+use crate::draw::*;
+use crate::layout;
+use crate::prefs::{self, Prefs};
+use crate::telnet;
+use crate::theme;
+use crate::users::Player;
+use crate::transport::Transport;
+
+fn cycle<T: PartialEq + Clone>(options: &[T], current: &T) -> T {
+    let i = options.iter().position(|o| o == current).map_or(0, |i| (i + 1) % options.len());
+    options[i].clone()
+}
+
+/// The Settings screen, drawn again after every change.  Screen readers
+/// get it as more lines rather than a cleared screen.
+pub fn draw(s: &mut dyn Transport, p: &Player, prefs: &Prefs) {
+    if prefs.accessible {
+        s.write(b"\r\n").unwrap();
+    } else {
+        cls(s);
+        pos(s, Point::new(1,1));
+    }
+    s.write(format!("Settings for {}\r\n\r\n", p.name).as_bytes()).unwrap();
+    s.write(format!("[1] Keys:           {} ({})\r\n", prefs.keymap.name(), prefs.keymap.describe()).as_bytes()).unwrap();
+    s.write(format!("[2] Color theme:    {}\r\n", prefs.theme).as_bytes()).unwrap();
+    s.write(format!("[3] Colors:         {}\r\n", prefs.colors).as_bytes()).unwrap();
+    s.write(format!("[4] Ghost piece:    {}\r\n", if prefs.ghost { "on" } else { "off" }).as_bytes()).unwrap();
+    let window = match telnet::window_size() {
+        Some((w, h)) if prefs.layout == "auto" => format!(" (your window is {}x{})", w, h),
+        _ => String::new()
+    };
+    s.write(format!("[5] Layout:         {}{}\r\n", prefs.layout, window).as_bytes()).unwrap();
+    s.write(format!("[6] Glyphs:         {}\r\n", prefs.glyphs).as_bytes()).unwrap();
+    s.write(format!("[7] Starting level: {}\r\n", prefs.start_level + 1).as_bytes()).unwrap();
+    s.write(format!("[8] Game mode:      {}\r\n", prefs.mode).as_bytes()).unwrap();
+    s.write(format!("[9] Screen reader:  {}\r\n", if prefs.accessible { "on" } else { "off" }).as_bytes()).unwrap();
+    s.write(format!("[a] Animations:     {}\r\n", if prefs.animations { "on" } else { "off" }).as_bytes()).unwrap();
+    if prefs.colors() != prefs.colors {
+        s.write(b"\r\nYour terminal says it can't do colors, so shapes are drawn as letters.\r\n").unwrap();
+    } else if prefs.colors == "none" {
+        s.write(b"\r\nWithout colors shapes are drawn as letters.\r\n").unwrap();
+    } else if prefs.glyphs != layout::STYLES[0] {
+        s.write(b"\r\nBlock glyphs need a UTF-8 terminal; halfblocks fits an 80x24 screen.\r\n").unwrap();
+    }
+    s.write(b"\r\nPress a number or letter to change a setting, [q] to go back.\r\n").unwrap();
+    if !p.registered {
+        s.write(b"Guests' settings are forgotten when they disconnect.\r\n").unwrap();
+    }
+    s.flush().unwrap();
+}
+
+/// Steps the setting `c` stands for to its next choice.  True on the way
+/// out, once everything is saved.
+pub fn key(p: &Player, prefs: &mut Prefs, c: u8) -> bool {
+    match c {
+        b'1' => prefs.keymap = cycle(prefs::KEYMAPS, &prefs.keymap),
+        b'2' => prefs.theme = cycle(&theme::names(), &prefs.theme.as_str()).to_string(),
+        b'3' => prefs.colors = cycle(prefs::COLORS, &prefs.colors.as_str()).to_string(),
+        b'4' => prefs.ghost = !prefs.ghost,
+        b'5' => prefs.layout = cycle(prefs::LAYOUTS, &prefs.layout.as_str()).to_string(),
+        b'6' => prefs.glyphs = cycle(layout::STYLES, &prefs.glyphs.as_str()).to_string(),
+        b'7' => prefs.start_level = (prefs.start_level + 1) % (prefs::MAX_START_LEVEL + 1),
+        b'8' => prefs.mode = cycle(prefs::MODES, &prefs.mode.as_str()).to_string(),
+        b'9' => prefs.accessible = !prefs.accessible,
+        b'a' => prefs.animations = !prefs.animations,
+        b'q' => {
+            prefs::save(p, prefs);
+            return true;
+        },
+        _ => {}
+    }
+    false
+}
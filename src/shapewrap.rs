@@ -1,64 +1,240 @@
 use tetrix::shape::{Shape, Orientation};
+use crate::grid::Cell;
+use crate::layout::{self, Glyph, Layout, Style};
 
-#[derive(Debug)]
-pub struct ShapeRep {
-    pub bytes: &'static [u8],
-    pub width: u8,
-    pub color_code: &'static str
-}
-
-pub const SINGLE : ShapeRep = ShapeRep {bytes: b"* ** *", width: 3, color_code: "0;0m"};
-
-pub fn shape_rep(s: Shape, o: Orientation) -> ShapeRep {
+/// Which cells each shape covers in each orientation, top row first, as
+/// the library places them: `to` in a `ShapePosition` is the bottom-left
+/// corner of this box.  Everything drawn for a piece comes from here, so
+/// there is one table to keep in step with the library rather than one
+/// per glyph style.  The library doesn't hand its shapes out, so the
+/// tests play it and check this against the cells each piece locks into.
+fn occupancy(s: Shape, o: Orientation) -> &'static [&'static str] {
     match s {
         Shape::Eye => match o {
-            Orientation::Up => ShapeRep {bytes: b"* ** ** ** ** ** ** ** *", width: 3, color_code: "1;31m"},
-            Orientation::Down => ShapeRep {bytes: b"* ** ** ** ** ** ** ** *", width: 3, color_code: "1;31m"},
-            Orientation::Left => ShapeRep {bytes: b"* * * * * * * ** * * * * * * *", width: 15, color_code: "1;31m"},
-            Orientation::Right => ShapeRep {bytes: b"* * * * * * * ** * * * * * * *", width: 15, color_code: "1;31m"},
+            Orientation::Up | Orientation::Down => &["#", "#", "#", "#"],
+            Orientation::Left | Orientation::Right => &["####"]
         },
         Shape::El => match o {
-            Orientation::Up    => ShapeRep {bytes: b"* *    * *    * *    * *    * * * ** * * *", width: 7, color_code: "1;34m"},
-            Orientation::Right => ShapeRep {bytes: b"* * * * * ** * * * * ** *        * *        ", width: 11, color_code: "1;34m"},
-            Orientation::Down  => ShapeRep {bytes: b"* * * ** * * *    * *    * *    * *    * *", width: 7, color_code: "1;34m"},
-            Orientation::Left  => ShapeRep {bytes: b"        * *        * ** * * * * ** * * * * *", width: 11, color_code: "1;34m"},            
+            Orientation::Up    => &["#.", "#.", "##"],
+            Orientation::Right => &["###", "#.."],
+            Orientation::Down  => &["##", ".#", ".#"],
+            Orientation::Left  => &["..#", "###"]
         },
         Shape::ElInv => match o {
-            Orientation::Up    => ShapeRep {bytes: b"    * *    * *    * *    * ** * * ** * * *", width: 7, color_code: "1;35m"},           
-            Orientation::Left  => ShapeRep {bytes: b"* * * * * ** * * * * *        * *        * *", width: 11, color_code: "1;35m"},
-            Orientation::Down  => ShapeRep {bytes: b"* * * ** * * ** *    * *    * *    * *    ", width: 7, color_code: "1;35m"},
-            Orientation::Right => ShapeRep {bytes: b"* *        * *        * * * * * ** * * * * *", width: 11, color_code: "1;35m"},
+            Orientation::Up    => &[".#", ".#", "##"],
+            Orientation::Left  => &["###", "..#"],
+            Orientation::Down  => &["##", "#.", "#."],
+            Orientation::Right => &["#..", "###"]
         },
         Shape::Zee => match o {
-            Orientation::Up    => ShapeRep {bytes: b"    * *    * ** * * ** * * ** *    * *    ", width: 7, color_code: "1;36m"},
-            Orientation::Left  => ShapeRep {bytes: b"* * * *    * * * *        * * * *    * * * *", width: 11, color_code: "1;36m"},
-            Orientation::Down  => ShapeRep {bytes: b"    * *    * ** * * ** * * ** *    * *    ", width: 7, color_code: "1;36m"},
-            Orientation::Right => ShapeRep {bytes: b"* * * *    * * * *        * * * *    * * * *", width: 11, color_code: "1;36m"},
+            Orientation::Up | Orientation::Down => &[".#", "##", "#."],
+            Orientation::Left | Orientation::Right => &["##.", ".##"]
         },
         Shape::ZeeInv => match o {
-            /*
-                * * * *
-                * * * *
-            * * * *    
-            * * * *     
-            */
-            Orientation::Left   => ShapeRep {bytes: b"    * * * *    * * * ** * * *    * * * *    ", width: 11, color_code: "1;37m"},
-            Orientation::Right  => ShapeRep {bytes: b"    * * * *    * * * ** * * *    * * * *    ", width: 11, color_code: "1;37m"},
-            Orientation::Up     => ShapeRep {bytes: b"* *    * *    * * * ** * * *    * *    * *", width: 7, color_code: "1;37m"},
-            Orientation::Down   => ShapeRep {bytes: b"* *    * *    * * * ** * * *    * *    * *", width: 7, color_code: "1;37m"},
-        },
-        Shape::Square => match o {
-            _ => ShapeRep {bytes: b"* * * ** * * ** * * ** * * *", width: 7, color_code: "1;33m"}
+            Orientation::Up | Orientation::Down => &["#.", "##", ".#"],
+            Orientation::Left | Orientation::Right => &[".##", "##."]
         },
+        Shape::Square => &["##", "##"],
         Shape::Tee => match o {
-            Orientation::Down    => ShapeRep {bytes: b"* * * * * ** * * * * *    * *        * *    ", width: 11, color_code: "1;32m"},
-            Orientation::Left  => ShapeRep {bytes: b"    * *    * ** * * ** * * *    * *    * *", width: 7, color_code: "1;32m"},
-            Orientation::Up  =>   ShapeRep {bytes: b"    * *        * *    * * * * * ** * * * * *", width: 11, color_code: "1;32m"},
-            Orientation::Right => ShapeRep {bytes: b"* *    * *    * * * ** * * ** *    * *    ", width: 7, color_code: "1;32m"}
+            Orientation::Up    => &[".#.", "###"],
+            Orientation::Right => &["#.", "##", "#."],
+            Orientation::Down  => &["###", ".#."],
+            Orientation::Left  => &[".#", "##", ".#"]
         }
     }
 }
 
-pub fn shape_color(s: Shape) -> &'static str {
-    return shape_rep(s, Orientation::Up).color_code;
-}
\ No newline at end of file
+/// The board cells a piece covers, as (x, y) offsets from its bottom-left
+/// corner.
+pub fn cells(s: Shape, o: Orientation) -> Vec<(usize, usize)> {
+    let rows = occupancy(s, o);
+    let mut out = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            if c == '#' {
+                out.push((x, rows.len() - 1 - i));
+            }
+        }
+    }
+    out
+}
+
+/// A piece drawn on its own, in the layout's cell size and glyph style.
+pub fn glyph(s: Shape, o: Orientation, l: &Layout) -> Glyph {
+    let mut g = Glyph::new(&cells(s, o), l);
+    if l.style == Style::Letters {
+        let c = layout::letter(Cell::Block(s));
+        for u in g.lines.iter_mut().flat_map(|line| line.iter_mut()).filter(|u| u.is_some()) {
+            *u = Some(c);
+        }
+    }
+    g
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tetrix::GameWrapper;
+    use tetrix::board::Board;
+    use tetrix::event::{Input, Output};
+    use crate::codec::{self, SHAPES};
+    use crate::layout;
+
+    const ORIENTATIONS: [Orientation; 4] = [Orientation::Up, Orientation::Right, Orientation::Down, Orientation::Left];
+
+    // reads the cells back off a glyph
+    fn covered(g: &Glyph, l: &Layout) -> Vec<(usize, usize)> {
+        let mut out = Vec::new();
+        if l.half {
+            let h = g.lines.len() * 2;
+            for (i, line) in g.lines.iter().enumerate() {
+                for (col, c) in line.iter().enumerate().step_by(l.cell_w) {
+                    let x = col / l.cell_w;
+                    let (upper, lower) = (h - 1 - 2 * i, h - 2 - 2 * i);
+                    if *c == Some(layout::FULL_BLOCK) || *c == Some(layout::UPPER_HALF) {
+                        out.push((x, upper));
+                    }
+                    if *c == Some(layout::FULL_BLOCK) || *c == Some(layout::LOWER_HALF) {
+                        out.push((x, lower));
+                    }
+                }
+            }
+            // an odd-height shape has an empty bottom half on its last line
+            let shift = if out.iter().any(|c| c.1 == 0) { 0 } else { 1 };
+            return out.into_iter().map(|(x, y)| (x, y - shift)).collect();
+        }
+        let h = g.lines.len() / l.cell_h;
+        for (i, line) in g.lines.iter().enumerate().step_by(l.cell_h) {
+            for (col, c) in line.iter().enumerate().step_by(l.cell_w) {
+                if c.is_some() {
+                    out.push((col / l.cell_w, h - 1 - i / l.cell_h));
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn glyphs_match_occupancy() {
+        let styles = [Style::Stars, Style::Blocks, Style::Brackets, Style::HalfBlocks, Style::Letters];
+        for (name, style) in ["big", "compact"].iter().flat_map(|n| styles.iter().map(move |s| (n, s))) {
+            let l = Layout::new(name, *style);
+            for s in SHAPES.iter() {
+                for o in ORIENTATIONS.iter() {
+                    let mut want = cells(*s, *o);
+                    let mut got = covered(&glyph(*s, *o, &l), &l);
+                    want.sort();
+                    got.sort();
+                    assert_eq!(got, want, "{:?} {:?} in {} {:?}", s, o, name, style);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn occupancy_is_rectangular_with_four_cells() {
+        for s in SHAPES.iter() {
+            for o in ORIENTATIONS.iter() {
+                let rows = occupancy(*s, *o);
+                assert!(rows.iter().all(|r| r.len() == rows[0].len()), "{:?} {:?}", s, o);
+                assert_eq!(cells(*s, *o).len(), 4, "{:?} {:?}", s, o);
+            }
+        }
+    }
+
+    #[test]
+    fn stars_match_the_old_glyphs() {
+        let l = Layout::new("big", Style::Stars);
+        let old: &[(Shape, Orientation, &[u8], usize)] = &[
+            (Shape::Eye, Orientation::Up, b"* ** ** ** ** ** ** ** *", 3),
+            (Shape::Eye, Orientation::Left, b"* * * * * * * ** * * * * * * *", 15),
+            (Shape::El, Orientation::Up, b"* *    * *    * *    * *    * * * ** * * *", 7),
+            (Shape::El, Orientation::Right, b"* * * * * ** * * * * ** *        * *        ", 11),
+            (Shape::El, Orientation::Down, b"* * * ** * * *    * *    * *    * *    * *", 7),
+            (Shape::El, Orientation::Left, b"        * *        * ** * * * * ** * * * * *", 11),
+            (Shape::ElInv, Orientation::Up, b"    * *    * *    * *    * ** * * ** * * *", 7),
+            (Shape::ElInv, Orientation::Left, b"* * * * * ** * * * * *        * *        * *", 11),
+            (Shape::ElInv, Orientation::Down, b"* * * ** * * ** *    * *    * *    * *    ", 7),
+            (Shape::ElInv, Orientation::Right, b"* *        * *        * * * * * ** * * * * *", 11),
+            (Shape::Zee, Orientation::Up, b"    * *    * ** * * ** * * ** *    * *    ", 7),
+            (Shape::Zee, Orientation::Left, b"* * * *    * * * *        * * * *    * * * *", 11),
+            (Shape::ZeeInv, Orientation::Left, b"    * * * *    * * * ** * * *    * * * *    ", 11),
+            (Shape::ZeeInv, Orientation::Up, b"* *    * *    * * * ** * * *    * *    * *", 7),
+            (Shape::Square, Orientation::Up, b"* * * ** * * ** * * ** * * *", 7),
+            (Shape::Tee, Orientation::Down, b"* * * * * ** * * * * *    * *        * *    ", 11),
+            (Shape::Tee, Orientation::Left, b"    * *    * ** * * ** * * *    * *    * *", 7),
+            (Shape::Tee, Orientation::Up, b"    * *        * *    * * * * * ** * * * * *", 11),
+            (Shape::Tee, Orientation::Right, b"* *    * *    * * * ** * * ** *    * *    ", 7)
+        ];
+        for (s, o, bytes, width) in old.iter() {
+            let lines: Vec<Vec<Option<char>>> = bytes.chunks(*width)
+                .map(|row| row.iter().map(|b| if *b == b'*' { Some('*') } else { None }).collect())
+                .collect();
+            assert_eq!(glyph(*s, *o, &l).lines, lines, "{:?} {:?}", s, o);
+        }
+    }
+
+    fn filled(b: &Board) -> HashSet<(usize, usize)> {
+        let mut out = HashSet::new();
+        for y in 0..tetrix::HEIGHT {
+            for x in 0..tetrix::WIDTH {
+                if b.0[y][x].is_some() {
+                    out.insert((x, y));
+                }
+            }
+        }
+        out
+    }
+
+    // each piece is turned a different number of times before it's dropped,
+    // until every shape has locked in every orientation
+    #[test]
+    fn occupancy_matches_the_library() {
+        let g = GameWrapper::new(tetrix::game());
+        let q = g.queue();
+        let mut settled = HashSet::new();
+        let mut active = None;
+        let mut dealt: HashMap<&str, usize> = HashMap::new();
+        let mut checked = HashSet::new();
+        let started = Instant::now();
+        g.send(Input::StartGame);
+        while checked.len() < SHAPES.len() * ORIENTATIONS.len() {
+            assert!(started.elapsed() < Duration::from_secs(60), "only saw {:?} lock", checked);
+            for evt in GameWrapper::drain(q.clone()) {
+                match evt {
+                    Output::GameStarted => settled.clear(),
+                    Output::GameOver => g.send(Input::StartGame),
+                    Output::ShapePosition(s, _, o, from, to) => {
+                        active = Some((s, o, to));
+                        if from.is_none() {
+                            let turns = dealt.entry(codec::shape_name(s)).or_insert(0);
+                            for _ in 0..*turns % 4 {
+                                g.send(Input::Cw);
+                            }
+                            g.send(Input::Drop);
+                            *turns += 1;
+                        }
+                    },
+                    Output::ShapeLocked(_, board) => {
+                        let now = filled(&board);
+                        if let Some((s, o, p)) = active.take() {
+                            let mut got: Vec<(usize, usize)> = now.difference(&settled).cloned().collect();
+                            let mut want: Vec<(usize, usize)> = cells(s, o).iter().map(|&(x, y)| (p.x + x, p.y + y)).collect();
+                            got.sort();
+                            want.sort();
+                            assert_eq!(got, want, "{:?} {:?} locked at {:?}", s, o, p);
+                            checked.insert((codec::shape_name(s), codec::orientation_name(o)));
+                        }
+                        settled = now;
+                    },
+                    Output::LineCompleted(_, board) => settled = filled(&board),
+                    _ => {}
+                }
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use tetrix::board::Board;
+use tetrix::shape::{Shape, Orientation, Point};
+use tetrix::event::Output;
+use crate::codec;
+use crate::prefs::Prefs;
+use crate::view;
+use crate::transport::Transport;
+
+/// Keys that ask the narrator something; none of them is bound by a keymap.
+pub const QUERY_KEYS: &[u8] = b"?bnc";
+
+fn say(s: &mut dyn Transport, text: &str) {
+    s.write(text.as_bytes()).unwrap();
+    s.write(b"\r\n").unwrap();
+}
+
+fn piece(shape: Shape) -> String {
+    format!("{} piece", codec::shape_letter(shape))
+}
+
+fn turned(o: Orientation) -> &'static str {
+    match o {
+        Orientation::Up => "not rotated",
+        Orientation::Right => "rotated right",
+        Orientation::Down => "upside down",
+        Orientation::Left => "rotated left"
+    }
+}
+
+// how tall the stack is in each column
+fn heights(b: &Board) -> Vec<usize> {
+    (0..tetrix::WIDTH).map(|x| {
+        (0..tetrix::HEIGHT).rev().find(|&y| b.0[y][x].is_some()).map_or(0, |y| y + 1)
+    }).collect()
+}
+
+// "1 to 3, 6" for the filled columns of a row
+fn runs(cols: &[usize]) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < cols.len() {
+        let mut j = i;
+        while j + 1 < cols.len() && cols[j + 1] == cols[j] + 1 {
+            j += 1;
+        }
+        out.push(if i == j { format!("{}", cols[i] + 1) } else { format!("{} to {}", cols[i] + 1, cols[j] + 1) });
+        i = j + 1;
+    }
+    out.join(", ")
+}
+
+/// The game told in words for screen readers: a line per thing worth
+/// knowing, nothing positioned, and the rest on request.  Fed the same
+/// events as `GameView`.
+pub struct Narrator {
+    n: String,
+    prefs: Prefs,
+    settled: Board,
+    active: Option<(Shape, Orientation, Point)>,
+    next_shape: Option<Shape>,
+    held_shape: Option<Shape>,
+    lines: u32,
+    lvl: u8
+}
+
+impl Narrator {
+    pub fn new(n: &str, prefs: &Prefs) -> Narrator {
+        Narrator {
+            n: n.to_string(),
+            prefs: prefs.clone(),
+            settled: Board::new(),
+            active: None,
+            next_shape: None,
+            held_shape: None,
+            lines: 0,
+            lvl: prefs.start_level
+        }
+    }
+
+    pub fn board(&self) -> Board {
+        self.settled
+    }
+
+    fn stack_height(&self) -> String {
+        let h = heights(&self.settled);
+        let top = h.iter().cloned().max().unwrap_or(0);
+        match h.iter().position(|&c| c == top) {
+            Some(x) if top > 0 => format!("stack height {} in column {}", top, x + 1),
+            _ => "board empty".to_string()
+        }
+    }
+
+    pub fn handle(&mut self, s: &mut dyn Transport, evt: &Output) {
+        match evt {
+            Output::GameStarted => {
+                *self = Narrator::new(&self.n, &self.prefs);
+                say(s, &format!("Game started at level {}. Press ? for what you can ask.", self.lvl + 1));
+            },
+            Output::GameOver => {
+                say(s, &format!("Game over with {} lines.", self.lines));
+            },
+            Output::HeldShape(shape) => {
+                say(s, &format!("Holding {}.", piece(*shape)));
+                self.held_shape = Some(*shape);
+                self.active = None;
+            },
+            Output::NextShape(shape) => {
+                self.next_shape = Some(*shape);
+            },
+            Output::LineCompleted(count, board) => {
+                let count = *count as u32;
+                self.lines += count;
+                self.settled = *board;
+                let noun = if count == 1 { "line" } else { "lines" };
+                say(s, &format!("{} {} cleared, total {}.", count, noun, self.lines));
+            },
+            Output::ScoreUpdate(score) => {
+                let lvl = view::level(*score, self.prefs.start_level);
+                if lvl != self.lvl {
+                    self.lvl = lvl;
+                    say(s, &format!("Level {}.", lvl + 1));
+                }
+            },
+            Output::ShapeLocked(shape, board) => {
+                self.settled = *board;
+                self.active = None;
+                say(s, &format!("{} placed, {}.", piece(*shape), self.stack_height()));
+            },
+            Output::ShapePosition(shape, _, orientation, _, to) => {
+                // falling is left unsaid; only what the player did is told
+                let text = match self.active {
+                    Some((old, o, p)) if old == *shape => {
+                        if o != *orientation {
+                            Some(format!("{}, column {}.", turned(*orientation), to.x + 1))
+                        } else if p.x != to.x {
+                            Some(format!("Column {}.", to.x + 1))
+                        } else {
+                            None
+                        }
+                    },
+                    _ => Some(match orientation {
+                        Orientation::Up => format!("{}, column {}.", piece(*shape), to.x + 1),
+                        o => format!("{}, column {}, {}.", piece(*shape), to.x + 1, turned(*o))
+                    })
+                };
+                if let Some(text) = text {
+                    say(s, &text);
+                }
+                self.active = Some((*shape, *orientation, *to));
+            },
+            _ => {}
+        }
+        s.flush().unwrap();
+    }
+
+    /// Answers one of the `QUERY_KEYS`.
+    pub fn query(&self, s: &mut dyn Transport, key: u8) {
+        match key {
+            b'b' => self.describe_board(s),
+            b'n' => self.describe_pieces(s),
+            b'c' => self.describe_columns(s),
+            _ => {
+                say(s, "b: describe the board row by row. n: next and held pieces.");
+                say(s, "c: stack height of every column. q: quit.");
+                say(s, &format!("Keys: {}, arrow keys also move and drop.", self.prefs.keymap.describe()));
+            }
+        }
+        s.flush().unwrap();
+    }
+
+    fn describe_board(&self, s: &mut dyn Transport) {
+        let top = heights(&self.settled).into_iter().max().unwrap_or(0);
+        if top == 0 {
+            say(s, "The board is empty.");
+        }
+        for y in (0..top).rev() {
+            let cols: Vec<usize> = (0..tetrix::WIDTH).filter(|&x| self.settled.0[y][x].is_some()).collect();
+            if cols.is_empty() {
+                say(s, &format!("Row {}: empty.", y + 1));
+            } else {
+                say(s, &format!("Row {}: columns {}.", y + 1, runs(&cols)));
+            }
+        }
+        if let Some((shape, o, p)) = self.active {
+            say(s, &format!("Falling: {}, column {}, row {}, {}.", piece(shape), p.x + 1, p.y + 1, turned(o)));
+        }
+    }
+
+    fn describe_pieces(&self, s: &mut dyn Transport) {
+        match self.next_shape {
+            Some(shape) => say(s, &format!("Next: {}.", piece(shape))),
+            None => say(s, "No next piece yet.")
+        }
+        match self.held_shape {
+            Some(shape) => say(s, &format!("Held: {}.", piece(shape))),
+            None => say(s, "Nothing held.")
+        }
+    }
+
+    fn describe_columns(&self, s: &mut dyn Transport) {
+        let h: Vec<String> = heights(&self.settled).iter().map(|h| h.to_string()).collect();
+        say(s, &format!("Column heights, left to right: {}.", h.join(", ")));
+    }
+}
@@ -0,0 +1,265 @@
+use std::io::{self, BufRead, Read, Write};
+use std::mem;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+use json::JsonValue;
+use russh::{Channel, ChannelId, CryptoVec, Pty};
+use russh::keys::PublicKey;
+use russh::server::{self, Auth, Handle, Msg, Server as _, Session};
+use crate::session;
+use crate::telnet;
+use crate::transport::Transport;
+use crate::users::{self, Player};
+
+pub const PORT: u16 = 2222;
+const HOST_KEY: &str = "ssh_host_ed25519_key";
+
+// what the client sends, in the order it sent it
+enum Incoming {
+    Keys(Vec<u8>),
+    Window(u16, u16),
+    Term(String)
+}
+
+/// A shell channel as a `Transport`.  Window changes and the terminal
+/// type come in with the keys, and are put where the telnet options
+/// would have put them as they're read, on the session's own thread.
+struct Shell {
+    rx: Receiver<Incoming>,
+    keys: Vec<u8>,
+    out: Vec<u8>,
+    rt: tokio::runtime::Handle,
+    handle: Handle,
+    channel: ChannelId
+}
+
+impl BufRead for Shell {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.keys.is_empty() {
+            match self.rx.try_recv() {
+                Ok(Incoming::Keys(data)) => self.keys = data,
+                Ok(Incoming::Window(w, h)) => telnet::set_window_size((w, h)),
+                Ok(Incoming::Term(t)) => telnet::set_terminal_type(&t),
+                Err(TryRecvError::Empty) => return Err(io::ErrorKind::WouldBlock.into()),
+                // the client has gone
+                Err(TryRecvError::Disconnected) => return Err(io::ErrorKind::ConnectionAborted.into())
+            }
+        }
+        Ok(&self.keys)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.keys.drain(..amt);
+    }
+}
+
+impl Read for Shell {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let rest = self.fill_buf()?;
+            let n = rest.len().min(buf.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl Write for Shell {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.out.is_empty() {
+            return Ok(());
+        }
+        let data = CryptoVec::from(mem::take(&mut self.out));
+        self.rt.block_on(self.handle.data(self.channel, data))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+impl Transport for Shell {}
+
+impl Drop for Shell {
+    fn drop(&mut self) {
+        let _ = self.flush();
+        let _ = self.rt.block_on(self.handle.close(self.channel));
+    }
+}
+
+fn linked_keys(name: &str) -> Vec<PublicKey> {
+    users::get(name, "ssh_keys").members()
+        .filter_map(|k| PublicKey::from_openssh(k.as_str()?).ok())
+        .collect()
+}
+
+fn is_linked(name: &str, key: &PublicKey) -> bool {
+    linked_keys(name).iter().any(|k| k.key_data() == key.key_data())
+}
+
+fn link(name: &str, key: &PublicKey) {
+    let text = match key.to_openssh() {
+        Ok(text) => text,
+        Err(_) => return
+    };
+    let result = users::update(name, "ssh_keys", |v| {
+        let mut keys = if v.is_array() { v } else { JsonValue::new_array() };
+        keys.push(text).unwrap();
+        keys
+    });
+    match result {
+        Ok(_) => log::info!("[{}] linked an ssh key", name),
+        Err(e) => log::error!("[{}] could not link an ssh key: {}", name, e)
+    }
+}
+
+/// One SSH connection.  The SSH user name is the player name: a name
+/// nobody has registered plays as a guest straight away, a registered
+/// one needs its password or a key linked to it.  A key offered ahead
+/// of a correct password is linked, so next time the password isn't
+/// asked for.
+struct Client {
+    rt: tokio::runtime::Handle,
+    player: Option<Player>,
+    offered: Option<PublicKey>,
+    tx: Sender<Incoming>,
+    rx: Option<Receiver<Incoming>>
+}
+
+impl Client {
+    fn accept(&mut self, user: &str, registered: bool) -> Auth {
+        log::info!("{} logged in over ssh{}", user, if registered { "" } else { " as a guest" });
+        self.player = Some(Player { name: user.to_string(), registered });
+        Auth::Accept
+    }
+}
+
+impl server::Handler for Client {
+    type Error = russh::Error;
+
+    async fn auth_none(&mut self, user: &str) -> Result<Auth, Self::Error> {
+        if users::is_registered(user) {
+            return Ok(Auth::reject());
+        }
+        Ok(self.accept(user, false))
+    }
+
+    async fn auth_password(&mut self, user: &str, password: &str) -> Result<Auth, Self::Error> {
+        if !users::is_registered(user) {
+            return Ok(self.accept(user, false));
+        }
+        // the hash takes its time, so it's kept off the runtime's threads
+        let (name, pw) = (user.to_string(), password.to_string());
+        let ok = tokio::task::spawn_blocking(move || users::verify(&name, &pw)).await.unwrap_or(false);
+        if !ok {
+            log::info!("{} failed a password check over ssh", user);
+            return Ok(Auth::reject());
+        }
+        if let Some(key) = self.offered.take() {
+            link(user, &key);
+        }
+        Ok(self.accept(user, true))
+    }
+
+    async fn auth_publickey_offered(&mut self, user: &str, key: &PublicKey) -> Result<Auth, Self::Error> {
+        if !users::is_registered(user) || is_linked(user, key) {
+            return Ok(Auth::Accept);
+        }
+        self.offered = Some(key.clone());
+        Ok(Auth::reject())
+    }
+
+    async fn auth_publickey(&mut self, user: &str, key: &PublicKey) -> Result<Auth, Self::Error> {
+        if !users::is_registered(user) {
+            return Ok(self.accept(user, false));
+        }
+        if is_linked(user, key) {
+            return Ok(self.accept(user, true));
+        }
+        Ok(Auth::reject())
+    }
+
+    async fn channel_open_session(&mut self, _channel: Channel<Msg>, _session: &mut Session) -> Result<bool, Self::Error> {
+        // one game per connection
+        Ok(self.rx.is_some())
+    }
+
+    async fn pty_request(&mut self, _channel: ChannelId, term: &str, col_width: u32, row_height: u32,
+                         _pix_width: u32, _pix_height: u32, _modes: &[(Pty, u32)], _session: &mut Session) -> Result<(), Self::Error> {
+        let _ = self.tx.send(Incoming::Term(term.to_string()));
+        let _ = self.tx.send(Incoming::Window(col_width as u16, row_height as u16));
+        Ok(())
+    }
+
+    async fn window_change_request(&mut self, _channel: ChannelId, col_width: u32, row_height: u32,
+                                   _pix_width: u32, _pix_height: u32, _session: &mut Session) -> Result<(), Self::Error> {
+        let _ = self.tx.send(Incoming::Window(col_width as u16, row_height as u16));
+        Ok(())
+    }
+
+    async fn data(&mut self, _channel: ChannelId, data: &[u8], _session: &mut Session) -> Result<(), Self::Error> {
+        let _ = self.tx.send(Incoming::Keys(data.to_vec()));
+        Ok(())
+    }
+
+    async fn shell_request(&mut self, channel: ChannelId, session: &mut Session) -> Result<(), Self::Error> {
+        let (rx, player) = match (self.rx.take(), self.player.clone()) {
+            (Some(rx), Some(player)) => (rx, player),
+            _ => {
+                session.channel_failure(channel)?;
+                return Ok(());
+            }
+        };
+        session.channel_success(channel)?;
+        let shell = Shell { rx, keys: Vec::new(), out: Vec::new(), rt: self.rt.clone(), handle: session.handle(), channel };
+        // sessions block, so each gets a thread as it would over telnet
+        thread::spawn(move || session::run(Box::new(shell), Some(player)));
+        Ok(())
+    }
+}
+
+struct Listener {
+    rt: tokio::runtime::Handle
+}
+
+impl server::Server for Listener {
+    type Handler = Client;
+
+    fn new_client(&mut self, peer: Option<SocketAddr>) -> Client {
+        log::info!("New ssh connection from {:?}", peer);
+        let (tx, rx) = mpsc::channel();
+        Client { rt: self.rt.clone(), player: None, offered: None, tx, rx: Some(rx) }
+    }
+}
+
+/// Serves SSH on `PORT` until the process ends.  Without a host key in
+/// `ssh_host_ed25519_key` there is no listener; make one with
+/// `ssh-keygen -t ed25519 -f ssh_host_ed25519_key -N ''`.
+pub fn listen() {
+    let key = match russh::keys::load_secret_key(HOST_KEY, None) {
+        Ok(key) => key,
+        Err(e) => {
+            log::error!("not serving ssh, can't read {}: {}", HOST_KEY, e);
+            return;
+        }
+    };
+    let config = Arc::new(server::Config {
+        keys: vec![key],
+        auth_rejection_time: Duration::from_secs(1),
+        auth_rejection_time_initial: Some(Duration::from_secs(0)),
+        ..Default::default()
+    });
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    log::info!("Starting ssh service on 0.0.0.0 at port {}", PORT);
+    let mut listener = Listener { rt: rt.handle().clone() };
+    if let Err(e) = rt.block_on(listener.run_on_address(config, ("0.0.0.0", PORT))) {
+        log::error!("ssh service stopped: {}", e);
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+use std::time::Instant;
+use json::JsonValue;
+use tetrix::event::Output;
+use crate::codec;
+use crate::users::{self, Player};
+
+/// Lifetime totals for one player, kept in the user store under `stats`.
+/// `pieces` is keyed by shape name, `best` by game mode.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub games: u32,
+    pub lines: u32,
+    pub tetrises: u32,
+    pub play_ms: u64,
+    pub pieces: BTreeMap<String, u32>,
+    pub best: BTreeMap<String, u32>
+}
+
+fn counts_to_json(m: &BTreeMap<String, u32>) -> JsonValue {
+    let mut v = JsonValue::new_object();
+    for (k, n) in m {
+        v[k.as_str()] = (*n).into();
+    }
+    v
+}
+
+fn counts_from_json(v: &JsonValue) -> BTreeMap<String, u32> {
+    v.entries()
+        .filter_map(|(k, n)| n.as_u32().map(|n| (k.to_string(), n)))
+        .collect()
+}
+
+impl Stats {
+    pub fn to_json(&self) -> JsonValue {
+        json::object!{
+            "games": self.games,
+            "lines": self.lines,
+            "tetrises": self.tetrises,
+            "play_ms": self.play_ms,
+            "pieces": counts_to_json(&self.pieces),
+            "best": counts_to_json(&self.best)
+        }
+    }
+
+    pub fn from_json(v: &JsonValue) -> Stats {
+        Stats {
+            games: v["games"].as_u32().unwrap_or(0),
+            lines: v["lines"].as_u32().unwrap_or(0),
+            tetrises: v["tetrises"].as_u32().unwrap_or(0),
+            play_ms: v["play_ms"].as_u64().unwrap_or(0),
+            pieces: counts_from_json(&v["pieces"]),
+            best: counts_from_json(&v["best"])
+        }
+    }
+
+    pub fn total_pieces(&self) -> u32 {
+        self.pieces.values().sum()
+    }
+
+    pub fn pieces_per_second(&self) -> f64 {
+        if self.play_ms == 0 {
+            return 0.0;
+        }
+        self.total_pieces() as f64 / (self.play_ms as f64 / 1000.0)
+    }
+
+    fn add(&mut self, mode: &str, t: &Tally) {
+        self.games += 1;
+        self.lines += t.lines;
+        self.tetrises += t.tetrises;
+        self.play_ms += t.start.elapsed().as_millis() as u64;
+        for (shape, n) in &t.pieces {
+            *self.pieces.entry(shape.to_string()).or_insert(0) += n;
+        }
+        let best = self.best.entry(mode.to_string()).or_insert(0);
+        if t.score > *best {
+            *best = t.score;
+        }
+    }
+}
+
+/// The running count for the game in progress, fed from the same event
+/// stream the view draws from.
+pub struct Tally {
+    start: Instant,
+    score: u32,
+    lines: u32,
+    tetrises: u32,
+    pieces: BTreeMap<&'static str, u32>
+}
+
+impl Tally {
+    pub fn new() -> Tally {
+        Tally {
+            start: Instant::now(),
+            score: 0,
+            lines: 0,
+            tetrises: 0,
+            pieces: BTreeMap::new()
+        }
+    }
+
+    pub fn record(&mut self, evt: &Output) {
+        match evt {
+            Output::ShapeLocked(shape, _) => {
+                *self.pieces.entry(codec::shape_name(*shape)).or_insert(0) += 1;
+            },
+            Output::LineCompleted(count, _) => {
+                let count = *count as u32;
+                self.lines += count;
+                if count == 4 {
+                    self.tetrises += 1;
+                }
+            },
+            Output::ScoreUpdate(score) => self.score = *score,
+            _ => {}
+        }
+    }
+}
+
+pub fn load(name: &str) -> Option<Stats> {
+    if !users::is_registered(name) {
+        return None;
+    }
+    Some(Stats::from_json(&users::get(name, "stats")))
+}
+
+/// Folds a finished (or abandoned) game into the player's totals.  Guests
+/// don't have any.
+pub fn record(p: &Player, mode: &str, t: Option<Tally>) {
+    let t = match t {
+        Some(t) if p.registered => t,
+        _ => return
+    };
+    let result = users::update(&p.name, "stats", |v| {
+        let mut stats = Stats::from_json(&v);
+        stats.add(mode, &t);
+        stats.to_json()
+    });
+    match result {
+        Ok(_) => log::info!("[{}] stats updated", p.name),
+        Err(e) => log::error!("[{}] could not update stats: {}", p.name, e)
+    }
+}
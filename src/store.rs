@@ -0,0 +1,19 @@
+//! Where the server keeps its files: the user store and the replays, in
+//! the directory it was started from.  A thread can be given a directory
+//! of its own, which is how tests keep away from the real ones.
+use std::cell::RefCell;
+use std::path::PathBuf;
+
+thread_local! {
+    static ROOT: RefCell<PathBuf> = RefCell::new(PathBuf::new());
+}
+
+/// Where the file or directory `name` goes for this thread.
+pub fn path(name: &str) -> PathBuf {
+    ROOT.with(|r| r.borrow().join(name))
+}
+
+#[cfg(test)]
+pub fn set_root(dir: &std::path::Path) {
+    ROOT.with(|r| *r.borrow_mut() = dir.to_path_buf());
+}
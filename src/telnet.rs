@@ -0,0 +1,190 @@
+use std::cell::{Cell, RefCell};
+use std::io;
+use crate::transport::Transport;
+
+pub const IAC: u8 = 255;
+pub const DONT: u8 = 254;
+pub const DO: u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+pub const SB: u8 = 250;
+pub const SE: u8 = 240;
+
+pub const ECHO: u8 = 1;
+pub const SUPPRESS_GO_AHEAD: u8 = 3;
+pub const TTYPE: u8 = 24;
+pub const NAWS: u8 = 31;
+pub const LINEMODE: u8 = 34;
+
+// TTYPE subnegotiation
+const IS: u8 = 0;
+const SEND: u8 = 1;
+
+thread_local! {
+    // each connection has its own thread, so these are per client
+    static WINDOW: Cell<Option<(u16, u16)>> = Cell::new(None);
+    static TERMINAL: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// The client's window as (columns, lines), if it has told us.
+pub fn window_size() -> Option<(u16, u16)> {
+    WINDOW.with(|w| w.get())
+}
+
+/// For transports that find out the window size some other way.
+pub fn set_window_size(size: (u16, u16)) {
+    WINDOW.with(|w| w.set(Some(size)));
+}
+
+/// Ask the client to report its window size, now and whenever it changes.
+pub fn request_window_size(s: &mut dyn Transport) {
+    if !s.telnet() {
+        return;
+    }
+    s.write(&[IAC, DO, NAWS]).unwrap();
+    s.flush().unwrap();
+}
+
+/// The terminal type the client reported, lowercased.
+pub fn terminal_type() -> Option<String> {
+    TERMINAL.with(|t| t.borrow().clone())
+}
+
+/// For transports that find out the terminal type some other way.
+pub fn set_terminal_type(name: &str) {
+    TERMINAL.with(|t| *t.borrow_mut() = Some(name.to_lowercase()));
+}
+
+/// Whether the client said it can't do colors (or much of anything).
+pub fn is_dumb() -> bool {
+    terminal_type().map_or(false, |t| t == "dumb")
+}
+
+/// Ask the client what kind of terminal it is; the name itself is asked
+/// for once it agrees.
+pub fn request_terminal_type(s: &mut dyn Transport) {
+    if !s.telnet() {
+        return;
+    }
+    s.write(&[IAC, DO, TTYPE]).unwrap();
+    s.flush().unwrap();
+}
+
+/// Tell the client we'll do the echoing, which in practice means nobody does.
+pub fn hide_input(s: &mut dyn Transport) {
+    if !s.telnet() {
+        return;
+    }
+    s.write(&[IAC, WILL, ECHO]).unwrap();
+    s.flush().unwrap();
+}
+
+/// Keys one at a time as they're typed, and no local echo.
+pub fn character_mode(s: &mut dyn Transport) {
+    if !s.telnet() {
+        return;
+    }
+    s.write(&[IAC, WILL, ECHO, IAC, WILL, SUPPRESS_GO_AHEAD, IAC, WONT, LINEMODE]).unwrap();
+    s.flush().unwrap();
+}
+
+fn next_byte(s: &mut dyn Transport) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    crate::poll_read_exact(s, &mut buf)?;
+    Ok(buf[0])
+}
+
+fn subnegotiation(data: &[u8]) {
+    match data {
+        [NAWS, w1, w0, h1, h0] => {
+            let size = (u16::from_be_bytes([*w1, *w0]), u16::from_be_bytes([*h1, *h0]));
+            log::debug!("client window is {}x{}", size.0, size.1);
+            set_window_size(size);
+        },
+        [TTYPE, IS, name @ ..] => {
+            let name = String::from_utf8_lossy(name);
+            log::debug!("client terminal is {}", name);
+            set_terminal_type(&name);
+        },
+        _ => {}
+    }
+}
+
+/// Handles the rest of a telnet command once its IAC has been read.  An
+/// escaped 255 is the only thing that comes back.  Fails if the client goes
+/// partway through.
+pub fn command(s: &mut dyn Transport) -> io::Result<Option<u8>> {
+    match next_byte(s)? {
+        IAC => Ok(Some(IAC)),
+        WILL => {
+            if next_byte(s)? == TTYPE {
+                s.write(&[IAC, SB, TTYPE, SEND, IAC, SE]).unwrap();
+                s.flush().unwrap();
+            }
+            Ok(None)
+        },
+        DONT | DO | WONT => {
+            next_byte(s)?;
+            Ok(None)
+        },
+        SB => {
+            let mut data = Vec::new();
+            loop {
+                match next_byte(s)? {
+                    IAC => match next_byte(s)? {
+                        SE => break,
+                        b => data.push(b)
+                    },
+                    b => data.push(b)
+                }
+            }
+            subnegotiation(&data);
+            Ok(None)
+        },
+        _ => Ok(None)
+    }
+}
+
+/// Reads one line byte by byte, dropping any telnet commands the client
+/// sends along with it, which aren't valid UTF-8.  Once the client is in
+/// character mode, or if it isn't a telnet client at all, nothing is
+/// echoed unless `echo` is set.  Fails if the client goes before the end
+/// of the line.
+pub fn read_line_raw(s: &mut dyn Transport, echo: bool) -> io::Result<String> {
+    let mut line = Vec::new();
+    loop {
+        match next_byte(s)? {
+            IAC => if let Some(b) = command(s)? {
+                line.push(b);
+            },
+            b'\r' => {
+                // CR comes as CR LF or CR NUL; eat the second half if it's here
+                let pair = match s.fill_buf() {
+                    Ok(rest) => !rest.is_empty() && (rest[0] == b'\n' || rest[0] == 0),
+                    Err(_) => false
+                };
+                if pair {
+                    s.consume(1);
+                }
+                break;
+            },
+            b'\n' => break,
+            0 => {},
+            // backspace / delete, since the client isn't editing the line for us
+            8 | 127 => {
+                if line.pop().is_some() && echo {
+                    s.write(b"\x08 \x08").unwrap();
+                    s.flush().unwrap();
+                }
+            },
+            b => {
+                line.push(b);
+                if echo {
+                    s.write(&[b]).unwrap();
+                    s.flush().unwrap();
+                }
+            }
+        }
+    }
+    Ok(String::from_utf8_lossy(&line).trim().to_string())
+}
@@ -0,0 +1,228 @@
+use tetrix::shape::Shape;
+use crate::grid::Cell;
+
+/// One color in each of the depths a terminal might support: an index into
+/// the 16 basic colors (8-15 being the bright ones), an xterm 256-color index
+/// and a 24-bit value.
+#[derive(Debug, Clone, Copy)]
+pub struct Color {
+    pub ansi: u8,
+    pub x256: u8,
+    pub rgb: (u8, u8, u8)
+}
+
+const fn color(ansi: u8, x256: u8, rgb: (u8, u8, u8)) -> Color {
+    Color { ansi, x256, rgb }
+}
+
+/// Shape colors are in `codec::SHAPES` order: Eye, El, ElInv, Zee, ZeeInv,
+/// Square, Tee.
+#[derive(Debug)]
+pub struct Theme {
+    pub name: &'static str,
+    pub shapes: [Color; 7],
+    pub border: Color,
+    pub background: Color,
+    pub text: Color,
+    pub ghost: Color
+}
+
+pub const CLASSIC: Theme = Theme {
+    name: "classic",
+    shapes: [
+        color(9, 196, (255, 85, 85)),
+        color(12, 63, (85, 85, 255)),
+        color(13, 207, (255, 85, 255)),
+        color(14, 87, (85, 255, 255)),
+        color(15, 231, (255, 255, 255)),
+        color(11, 227, (255, 255, 85)),
+        color(10, 83, (85, 255, 85))
+    ],
+    border: color(10, 83, (85, 255, 85)),
+    background: color(0, 16, (0, 0, 0)),
+    text: color(7, 250, (192, 192, 192)),
+    ghost: color(8, 240, (85, 85, 85))
+};
+
+pub const GUIDELINE: Theme = Theme {
+    name: "guideline",
+    shapes: [
+        color(14, 51, (0, 240, 240)),
+        color(3, 208, (240, 160, 0)),
+        color(12, 21, (0, 0, 240)),
+        color(9, 196, (240, 0, 0)),
+        color(10, 46, (0, 240, 0)),
+        color(11, 226, (240, 240, 0)),
+        color(5, 129, (160, 0, 240))
+    ],
+    border: color(7, 248, (170, 170, 170)),
+    background: color(0, 16, (0, 0, 0)),
+    text: color(15, 255, (238, 238, 238)),
+    ghost: color(8, 239, (78, 78, 78))
+};
+
+pub const HIGH_CONTRAST: Theme = Theme {
+    name: "high-contrast",
+    shapes: [
+        color(14, 51, (0, 255, 255)),
+        color(15, 231, (255, 255, 255)),
+        color(12, 33, (0, 135, 255)),
+        color(9, 196, (255, 0, 0)),
+        color(10, 46, (0, 255, 0)),
+        color(11, 226, (255, 255, 0)),
+        color(13, 201, (255, 0, 255))
+    ],
+    border: color(15, 231, (255, 255, 255)),
+    background: color(0, 16, (0, 0, 0)),
+    text: color(15, 231, (255, 255, 255)),
+    ghost: color(7, 246, (148, 148, 148))
+};
+
+pub const MONOCHROME: Theme = Theme {
+    name: "monochrome",
+    shapes: [
+        color(15, 255, (238, 238, 238)),
+        color(7, 252, (208, 208, 208)),
+        color(15, 249, (178, 178, 178)),
+        color(7, 246, (148, 148, 148)),
+        color(15, 243, (118, 118, 118)),
+        color(7, 254, (228, 228, 228)),
+        color(15, 250, (188, 188, 188))
+    ],
+    border: color(7, 250, (188, 188, 188)),
+    background: color(0, 16, (0, 0, 0)),
+    text: color(7, 250, (188, 188, 188)),
+    ghost: color(8, 238, (68, 68, 68))
+};
+
+pub const THEMES: &[&Theme] = &[&CLASSIC, &GUIDELINE, &HIGH_CONTRAST, &MONOCHROME];
+
+pub fn names() -> Vec<&'static str> {
+    THEMES.iter().map(|t| t.name).collect()
+}
+
+pub fn theme(name: &str) -> &'static Theme {
+    THEMES.iter().find(|t| t.name == name).cloned().unwrap_or(&CLASSIC)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Depth {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+    // no SGR at all, for terminals that would print it
+    Plain
+}
+
+impl Depth {
+    pub fn from_name(name: &str) -> Depth {
+        match name {
+            "256" => Depth::Ansi256,
+            "truecolor" => Depth::TrueColor,
+            "none" => Depth::Plain,
+            _ => Depth::Ansi16
+        }
+    }
+}
+
+fn fg(c: Color, depth: Depth) -> String {
+    match depth {
+        Depth::Ansi16 if c.ansi >= 8 => format!("1;3{}", c.ansi - 8),
+        Depth::Ansi16 => format!("3{}", c.ansi),
+        Depth::Ansi256 => format!("38;5;{}", c.x256),
+        Depth::TrueColor => format!("38;2;{};{};{}", c.rgb.0, c.rgb.1, c.rgb.2),
+        Depth::Plain => String::new()
+    }
+}
+
+fn bg(c: Color, depth: Depth) -> String {
+    match depth {
+        Depth::Ansi16 => format!("4{}", c.ansi % 8),
+        Depth::Ansi256 => format!("48;5;{}", c.x256),
+        Depth::TrueColor => format!("48;2;{};{};{}", c.rgb.0, c.rgb.1, c.rgb.2),
+        Depth::Plain => String::new()
+    }
+}
+
+/// A theme resolved to the SGR parameters (everything after `ESC [`) the
+/// drawing code writes.  Each starts with a reset so nothing leaks over
+/// from whatever was drawn before.  With `Depth::Plain` they are all empty,
+/// which `sgr` takes to mean "write nothing".
+#[derive(Debug, Clone)]
+pub struct Palette {
+    shapes: Vec<String>,
+    // bare fg/bg parameters, for half-block cells that mix two colors
+    fgs: Vec<String>,
+    bgs: Vec<String>,
+    ghost_fg: String,
+    ghost_bg: String,
+    back: String,
+    pub border: String,
+    pub field: String,
+    pub text: String,
+    pub ghost: String,
+    pub reset: String
+}
+
+impl Palette {
+    pub fn new(theme: &Theme, depth: Depth) -> Palette {
+        let back = bg(theme.background, depth);
+        let plain = depth == Depth::Plain;
+        let cell = |c: Color| if plain { String::new() } else { format!("0;{};{}m", fg(c, depth), back) };
+        Palette {
+            shapes: theme.shapes.iter().map(|c| cell(*c)).collect(),
+            fgs: theme.shapes.iter().map(|c| fg(*c, depth)).collect(),
+            bgs: theme.shapes.iter().map(|c| bg(*c, depth)).collect(),
+            ghost_fg: fg(theme.ghost, depth),
+            ghost_bg: bg(theme.ghost, depth),
+            back: back.clone(),
+            border: cell(theme.border),
+            field: if plain { String::new() } else { format!("0;{}m", back) },
+            text: if plain { String::new() } else { format!("0;{}m", fg(theme.text, depth)) },
+            ghost: cell(theme.ghost),
+            reset: if plain { String::new() } else { "0;0m".to_string() }
+        }
+    }
+
+    pub fn shape(&self, s: Shape) -> &str {
+        &self.shapes[index(s)]
+    }
+
+    pub fn cell(&self, c: Cell) -> &str {
+        match c {
+            Cell::Empty => &self.field,
+            Cell::Block(s) => self.shape(s),
+            Cell::Ghost(_) => &self.ghost
+        }
+    }
+
+    /// The bare foreground parameter for a cell, `None` if it is empty.
+    pub fn fg(&self, c: Cell) -> Option<&str> {
+        match c {
+            Cell::Empty => None,
+            Cell::Block(s) => Some(&self.fgs[index(s)]),
+            Cell::Ghost(_) => Some(&self.ghost_fg)
+        }
+    }
+
+    /// The bare background parameter for a cell; empty ones get the field's.
+    pub fn bg(&self, c: Cell) -> &str {
+        match c {
+            Cell::Empty => &self.back,
+            Cell::Block(s) => &self.bgs[index(s)],
+            Cell::Ghost(_) => &self.ghost_bg
+        }
+    }
+}
+
+fn index(s: Shape) -> usize {
+    match s {
+        Shape::Eye => 0,
+        Shape::El => 1,
+        Shape::ElInv => 2,
+        Shape::Zee => 3,
+        Shape::ZeeInv => 4,
+        Shape::Square => 5,
+        Shape::Tee => 6
+    }
+}
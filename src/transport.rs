@@ -0,0 +1,113 @@
+use std::io::{self, BufRead, Read, Write};
+use bufstream::BufStream;
+
+/// A two-way byte stream to one client: a telnet connection, a local
+/// terminal, a pipe in a test.  Sessions are written against this and the
+/// listener picks what's underneath.
+///
+/// Reads mustn't block for long.  With nothing to read they fail, usually
+/// with `WouldBlock`, and the session loop carries on drawing and tries
+/// again on its next pass.  Any other failure, or an end of file, means the
+/// client has gone.
+pub trait Transport: BufRead + Write {
+    /// Whether the other end is a telnet client, so options can be
+    /// negotiated with it.  Anything else gets plain bytes, echoes nothing
+    /// itself and has its window size found out some other way.
+    fn telnet(&self) -> bool {
+        false
+    }
+}
+
+/// Whether a failed read means the client has gone, rather than that
+/// nothing has come in yet.
+pub fn gone(e: &io::Error) -> bool {
+    !matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted)
+}
+
+impl<S: Read + Write> Transport for BufStream<S> {
+    fn telnet(&self) -> bool {
+        true
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn telnet(&self) -> bool {
+        (**self).telnet()
+    }
+}
+
+/// The session's side of a connection.  Once the client has gone, by an
+/// end of file or a read or write failing for good, reads fail with
+/// `ConnectionAborted`.  Writes that fail that way are thrown away, so
+/// drawing to a dead connection can't panic before the session has
+/// cleaned up after itself.
+pub struct Link {
+    inner: Box<dyn Transport>,
+    closed: bool
+}
+
+impl Link {
+    pub fn new(inner: Box<dyn Transport>) -> Link {
+        Link { inner, closed: false }
+    }
+}
+
+impl BufRead for Link {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.closed {
+            return Err(io::ErrorKind::ConnectionAborted.into());
+        }
+        let rest = self.inner.fill_buf();
+        match &rest {
+            Ok([]) => self.closed = true,
+            Err(e) if gone(e) => self.closed = true,
+            _ => {}
+        }
+        rest
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+    }
+}
+
+impl Read for Link {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let rest = self.fill_buf()?;
+            let n = rest.len().min(buf.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl Write for Link {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.inner.write(buf) {
+            Err(ref e) if gone(e) => {
+                self.closed = true;
+                Ok(buf.len())
+            },
+            r => r
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.inner.flush() {
+            Err(ref e) if gone(e) => {
+                self.closed = true;
+                Ok(())
+            },
+            r => r
+        }
+    }
+}
+
+impl Transport for Link {
+    fn telnet(&self) -> bool {
+        self.inner.telnet()
+    }
+}
@@ -0,0 +1,172 @@
+use std::fs;
+use std::io;
+use std::sync::Mutex;
+use json::JsonValue;
+use hmac::Hmac;
+use sha2::{Sha256, Digest};
+use subtle::ConstantTimeEq;
+use crate::store;
+
+pub const USERS_FILE: &str = "users.json";
+// OWASP's figure for PBKDF2-HMAC-SHA256
+const HASH_ROUNDS: u32 = 600_000;
+const LEGACY_ROUNDS: u32 = 10_000;
+
+// every session thread goes through here, so reads and writes of the store
+// happen one at a time.
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Who is on the other end of the connection.  Guests picked a name nobody
+/// has registered and never get written to the user store.
+#[derive(Debug, Clone)]
+pub struct Player {
+    pub name: String,
+    pub registered: bool
+}
+
+// names are matched case-insensitively so "Bob" can't squat on "bob".
+fn key(name: &str) -> String {
+    name.to_lowercase()
+}
+
+fn load() -> JsonValue {
+    match fs::read_to_string(store::path(USERS_FILE)) {
+        Ok(text) => match json::parse(&text) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("{} is corrupt, ignoring it: {}", USERS_FILE, e);
+                JsonValue::new_object()
+            }
+        },
+        Err(_) => JsonValue::new_object()
+    }
+}
+
+fn save(users: &JsonValue) -> io::Result<()> {
+    let tmp = store::path(&format!("{}.tmp", USERS_FILE));
+    fs::write(&tmp, users.pretty(2))?;
+    fs::rename(&tmp, store::path(USERS_FILE))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hash_password(salt: &str, password: &str, rounds: u32) -> String {
+    let mut out = [0; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt.as_bytes(), rounds, &mut out);
+    to_hex(&out)
+}
+
+// accounts stored without a round count were hashed like this before
+// PBKDF2; a good login rehashes them
+fn legacy_hash(salt: &str, password: &str) -> String {
+    let mut h = Sha256::new();
+    h.update(salt.as_bytes());
+    h.update(password.as_bytes());
+    let mut digest = h.finalize();
+    for _ in 1..LEGACY_ROUNDS {
+        let mut h = Sha256::new();
+        h.update(&digest);
+        h.update(salt.as_bytes());
+        digest = h.finalize();
+    }
+    to_hex(&digest)
+}
+
+pub fn is_registered(name: &str) -> bool {
+    let _guard = STORE_LOCK.lock().unwrap();
+    load().has_key(&key(name))
+}
+
+/// Returns `Ok(false)` if somebody registered the name first.
+pub fn register(name: &str, password: &str) -> io::Result<bool> {
+    if is_registered(name) {
+        return Ok(false);
+    }
+    // hashing takes a while, so it's done without holding up the store
+    let salt = to_hex(&rand::random::<[u8; 16]>());
+    let hash = hash_password(&salt, password, HASH_ROUNDS);
+    let _guard = STORE_LOCK.lock().unwrap();
+    let mut users = load();
+    let k = key(name);
+    if users.has_key(&k) {
+        return Ok(false);
+    }
+    users[k.as_str()] = json::object!{
+        "name": name,
+        "salt": salt,
+        "hash": hash,
+        "rounds": HASH_ROUNDS
+    };
+    save(&users)?;
+    Ok(true)
+}
+
+pub fn verify(name: &str, password: &str) -> bool {
+    let (salt, hash, rounds) = {
+        let _guard = STORE_LOCK.lock().unwrap();
+        let mut users = load();
+        let mut user = users.remove(&key(name));
+        match (user["salt"].take_string(), user["hash"].take_string()) {
+            (Some(salt), Some(hash)) => (salt, hash, user["rounds"].as_u32()),
+            _ => return false
+        }
+    };
+    let tried = match rounds {
+        Some(rounds) => hash_password(&salt, password, rounds),
+        None => legacy_hash(&salt, password)
+    };
+    if !bool::from(tried.as_bytes().ct_eq(hash.as_bytes())) {
+        return false;
+    }
+    if rounds != Some(HASH_ROUNDS) {
+        if let Err(e) = rehash(name, &hash, password) {
+            log::error!("could not rehash the password of {}: {}", name, e);
+        }
+    }
+    true
+}
+
+// stores the password hashed the current way, unless it changed meanwhile
+fn rehash(name: &str, old: &str, password: &str) -> io::Result<()> {
+    let salt = to_hex(&rand::random::<[u8; 16]>());
+    let hash = hash_password(&salt, password, HASH_ROUNDS);
+    let _guard = STORE_LOCK.lock().unwrap();
+    let mut users = load();
+    let user = &mut users[key(name).as_str()];
+    if user["hash"].as_str() != Some(old) {
+        return Ok(());
+    }
+    user["salt"] = salt.into();
+    user["hash"] = hash.into();
+    user["rounds"] = HASH_ROUNDS.into();
+    save(&users)
+}
+
+/// A per-user section of the store (`prefs`, ...), `Null` if unset.
+pub fn get(name: &str, field: &str) -> JsonValue {
+    let _guard = STORE_LOCK.lock().unwrap();
+    let mut users = load();
+    users[key(name).as_str()].remove(field)
+}
+
+/// Only registered users have somewhere to put this; for anyone else it's an error.
+pub fn set(name: &str, field: &str, value: JsonValue) -> io::Result<()> {
+    update(name, field, |_| value)
+}
+
+/// Read-modify-write of a per-user section, holding the store lock
+/// throughout so two sessions for the same user don't lose each other's
+/// changes.
+pub fn update<F: FnOnce(JsonValue) -> JsonValue>(name: &str, field: &str, f: F) -> io::Result<()> {
+    let _guard = STORE_LOCK.lock().unwrap();
+    let mut users = load();
+    let k = key(name);
+    if !users.has_key(&k) {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not registered", name)));
+    }
+    let old = users[k.as_str()].remove(field);
+    users[k.as_str()][field] = f(old);
+    save(&users)
+}
@@ -0,0 +1,379 @@
+use std::time::{Duration, Instant};
+use tetrix::board::Board;
+use tetrix::shape::{Shape, Orientation, Point};
+use tetrix::event::Output;
+use crate::shapewrap;
+use crate::draw::*;
+use crate::grid::{Cell, Grid};
+use crate::layout::{Glyph, Layout, ROWS};
+use crate::prefs::Prefs;
+use crate::speech::Narrator;
+use crate::theme::Palette;
+use crate::transport::Transport;
+
+const CLEAR_ANIMATION: Duration = Duration::from_millis(250);
+const LEVEL_FLASH: Duration = Duration::from_millis(600);
+const LEVEL_BLINK_MS: u128 = 100;
+const TOPOUT_ROW_MS: u128 = 40;
+
+/// The level a game is at after `score` lines.
+pub fn level(score: u32, start_level: u8) -> u8 {
+    ((score / 10) as u8).max(start_level)
+}
+
+/// The game as the player takes it in: drawn on screen, or told in words.
+pub enum Display {
+    Screen(GameView),
+    Words(Narrator)
+}
+
+impl Display {
+    pub fn new(n: &str, prefs: &Prefs) -> Display {
+        if prefs.accessible {
+            Display::Words(Narrator::new(n, prefs))
+        } else {
+            Display::Screen(GameView::new(n, prefs))
+        }
+    }
+
+    pub fn handle(&mut self, s: &mut dyn Transport, evt: &Output) {
+        match self {
+            Display::Screen(v) => v.handle(s, evt),
+            Display::Words(w) => w.handle(s, evt)
+        }
+    }
+
+    /// Moves any animation along; called every pass of the session loop.
+    pub fn tick(&mut self, s: &mut dyn Transport) {
+        if let Display::Screen(v) = self {
+            v.tick(s);
+        }
+    }
+
+    /// Whether something is playing out that the game-over screen should
+    /// wait for.
+    pub fn animating(&self) -> bool {
+        match self {
+            Display::Screen(v) => v.animating(),
+            Display::Words(_) => false
+        }
+    }
+
+    /// The screen from scratch; nothing to do when there's no screen.
+    pub fn redraw(&mut self, s: &mut dyn Transport) {
+        if let Display::Screen(v) = self {
+            v.redraw(s);
+        }
+    }
+
+    pub fn current_board(&self) -> Board {
+        match self {
+            Display::Screen(v) => v.current_board,
+            Display::Words(w) => w.board()
+        }
+    }
+}
+
+/// What the client currently has on screen for one game, and how to get from
+/// there to the next frame.  Fed the same `Output` events whether they come
+/// from a live `GameWrapper` or a replay file.
+pub struct GameView {
+    n: String,
+    prefs: Prefs,
+    pal: Palette,
+    pub layout: Layout,
+    grid: Grid,
+    // the locked cells; the falling piece and its ghost are drawn over these
+    settled: Board,
+    active: Option<(Shape, Orientation, Point)>,
+    ghost_at: Option<Point>,
+    pub current_board: Board,
+    next_shape: Option<Shape>,
+    held_shape: Option<Shape>,
+    next_pos: Option<Point>,
+    pub lvl: u8,
+    score: u32,
+    // animations: the rows being wiped and the board once they're gone,
+    // when the level went up, when the game was lost
+    clearing: Option<(Instant, Vec<usize>, Board)>,
+    level_flash: Option<Instant>,
+    topout: Option<Instant>
+}
+
+impl GameView {
+    pub fn new(n: &str, prefs: &Prefs) -> GameView {
+        GameView {
+            n: n.to_string(),
+            prefs: prefs.clone(),
+            pal: prefs.palette(),
+            layout: prefs.layout(),
+            grid: Grid::new(),
+            settled: Board::new(),
+            active: None,
+            ghost_at: None,
+            current_board: Board::new(),
+            next_shape: None,
+            held_shape: None,
+            next_pos: None,
+            lvl: prefs.start_level,
+            score: 0,
+            clearing: None,
+            level_flash: None,
+            topout: None
+        }
+    }
+
+    /// Where a piece with `cells` would come to rest if dropped from `p`.
+    fn ghost_point(&self, cells: &[(usize, usize)], p: Point) -> Point {
+        // mid-wipe the rows are as good as gone
+        let settled = self.clearing.as_ref().map_or(&self.settled, |c| &c.2);
+        let fits = |y: usize| cells.iter().all(|&(cx, cy)| {
+            let (x, y) = (p.x + cx, y + cy);
+            x < tetrix::WIDTH && (y >= tetrix::HEIGHT || settled.0[y][x].is_none())
+        });
+        let mut y = p.y;
+        while y > 0 && fits(y - 1) {
+            y -= 1;
+        }
+        Point::new(p.x, y)
+    }
+
+    // settled cells, then the ghost, then the falling piece on top
+    fn cells(&self) -> Vec<Cell> {
+        let w = tetrix::WIDTH;
+        let mut out = vec![Cell::Empty; ROWS * w];
+        for y in 0..ROWS.min(tetrix::HEIGHT) {
+            for x in 0..w {
+                if let Some(shape) = self.settled.0[y][x] {
+                    out[y * w + x] = Cell::Block(shape);
+                }
+            }
+        }
+        if let Some((start, rows, _)) = &self.clearing {
+            // wipe outwards from the middle
+            let done = start.elapsed().as_millis() * tetrix::WIDTH as u128 / CLEAR_ANIMATION.as_millis();
+            for &y in rows.iter().filter(|&&y| y < ROWS) {
+                for x in 0..w {
+                    let from_middle = (2 * x + 1).max(w) - (2 * x + 1).min(w);
+                    if (from_middle as u128) < done {
+                        out[y * w + x] = Cell::Empty;
+                    }
+                }
+            }
+        }
+        if let Some((shape, orientation, p)) = self.active {
+            let piece = shapewrap::cells(shape, orientation);
+            let mut put = |at: Point, c: Cell| {
+                for &(cx, cy) in &piece {
+                    let (x, y) = (at.x + cx, at.y + cy);
+                    if x < w && y < ROWS {
+                        out[y * w + x] = c;
+                    }
+                }
+            };
+            if let Some(gp) = self.ghost_at {
+                put(gp, Cell::Ghost(shape));
+            }
+            put(p, Cell::Block(shape));
+        }
+        if let Some(start) = self.topout {
+            // fill the well from the bottom up
+            let rows = ((start.elapsed().as_millis() / TOPOUT_ROW_MS) as usize + 1).min(ROWS);
+            for c in out.iter_mut().take(rows * w) {
+                *c = Cell::Ghost(Shape::Square);
+            }
+        }
+        out
+    }
+
+    fn finish_clearing(&mut self) {
+        if let Some((_, _, board)) = self.clearing.take() {
+            self.settled = board;
+        }
+    }
+
+    pub fn animating(&self) -> bool {
+        self.clearing.is_some() || self.topout.is_some()
+    }
+
+    pub fn tick(&mut self, s: &mut dyn Transport) {
+        if !self.animating() && self.level_flash.is_none() {
+            return;
+        }
+        if let Some(start) = self.level_flash {
+            let on = start.elapsed() < LEVEL_FLASH && (start.elapsed().as_millis() / LEVEL_BLINK_MS) % 2 == 0;
+            draw_level(s, &self.layout, self.lvl, &self.pal, on);
+            if start.elapsed() >= LEVEL_FLASH {
+                self.level_flash = None;
+            }
+        }
+        if self.clearing.as_ref().map_or(false, |c| c.0.elapsed() >= CLEAR_ANIMATION) {
+            self.finish_clearing();
+        }
+        self.sync(s);
+        if self.topout.map_or(false, |t| t.elapsed().as_millis() >= TOPOUT_ROW_MS * ROWS as u128) {
+            self.topout = None;
+        }
+        s.flush().unwrap();
+    }
+
+    fn sync(&mut self, s: &mut dyn Transport) {
+        let want = self.cells();
+        self.grid.sync(s, &self.layout, &self.pal, &want);
+    }
+
+    fn glyph(&self, shape: Shape) -> Glyph {
+        shapewrap::glyph(shape, Orientation::Up, &self.layout)
+    }
+
+    fn draw_held(&mut self, s: &mut dyn Transport, shape: Shape) {
+        let l = self.layout;
+        let g = self.glyph(shape);
+        draw_glyph(s, &g, Point::new(l.panel_x, l.held_bottom - g.height()), self.pal.shape(shape));
+        pos(s, Point::new(l.panel_x, l.held_label_row));
+        sgr(s, &self.pal.text);
+        s.write(b"Held Shape").unwrap();
+        self.held_shape = Some(shape);
+    }
+
+    fn draw_next(&mut self, s: &mut dyn Transport, shape: Shape) {
+        let l = self.layout;
+        let g = self.glyph(shape);
+        let top = l.next_bottom - g.height();
+        draw_glyph(s, &g, Point::new(l.panel_x, top), self.pal.shape(shape));
+        let p = Point::new(l.panel_x, top - l.next_label_gap);
+        pos(s, p);
+        sgr(s, &self.pal.text);
+        self.next_pos = Some(p);
+        s.write(b"Next shape").unwrap();
+        self.next_shape = Some(shape);
+    }
+
+    /// Paints everything again from what is known of the game rather than
+    /// from what was drawn before, for when the screen got garbled or the
+    /// window changed size.  The layout is picked again for the same reason.
+    pub fn redraw(&mut self, s: &mut dyn Transport) {
+        self.layout = self.prefs.layout();
+        cls(s);
+        draw_board(s, &self.layout, &self.pal);
+        draw_score(s, &self.layout, self.score, &self.pal);
+        draw_level(s, &self.layout, self.lvl, &self.pal, false);
+        self.grid.invalidate();
+        self.sync(s);
+        if let Some(shape) = self.next_shape {
+            self.draw_next(s, shape);
+        }
+        if let Some(shape) = self.held_shape {
+            self.draw_held(s, shape);
+        }
+        s.flush().unwrap();
+    }
+
+    pub fn handle(&mut self, s: &mut dyn Transport, evt: &Output) {
+        match evt {
+            Output::GameStarted => {
+                *self = GameView::new(&self.n, &self.prefs);
+                cls(s);
+                draw_board(s, &self.layout, &self.pal);
+                draw_score(s, &self.layout, 0, &self.pal);
+                draw_level(s, &self.layout, self.lvl, &self.pal, false);
+                s.flush().unwrap();
+            },
+            Output::GameOver => {
+                self.finish_clearing();
+                if self.prefs.animations {
+                    self.topout = Some(Instant::now());
+                }
+            },
+            Output::BoardUpdate(b) => {
+                self.current_board = *b;
+            },
+            Output::HeldShape(shape) => {
+                log::info!("[{}] held shape processed event: {:?}", self.n, shape);
+                let l = self.layout;
+                if let Some(old) = self.held_shape {
+                    let g = self.glyph(old);
+                    clear_glyph(s, &g, Point::new(l.panel_x, l.held_bottom - g.height()), &self.pal.text);
+                }
+                self.draw_held(s, *shape);
+
+                // the piece in play went into the hold
+                self.active = None;
+                self.ghost_at = None;
+                self.sync(s);
+                s.flush().unwrap();
+            },
+            Output::LineCompleted(count, board) => {
+                log::info!("[{}] line completion event: {}", self.n, count);
+                self.finish_clearing();
+                log::info!("old board: {}", self.settled.report());
+                log::info!("new board: {}", board.report());
+                let full: Vec<usize> = (0..tetrix::HEIGHT)
+                    .filter(|&y| (0..tetrix::WIDTH).all(|x| self.settled.0[y][x].is_some()))
+                    .collect();
+                if self.prefs.animations && !full.is_empty() {
+                    self.clearing = Some((Instant::now(), full, *board));
+                } else {
+                    self.settled = *board;
+                }
+                self.sync(s);
+                s.flush().unwrap();
+                log::info!("[{}] done handling line completion!", self.n);
+            },
+            Output::ScoreUpdate(score) => {
+                log::info!("[{}] score update: {}", self.n, score);
+                self.score = *score;
+                draw_score(s, &self.layout, *score, &self.pal);
+
+                let lvl = level(*score, self.prefs.start_level);
+                if lvl != self.lvl {
+                    log::debug!("score is {}, score / 10 is {}, lvl is {}", score, score / 10, self.lvl);
+                    self.lvl = lvl;
+                    log::debug!("updating level display");
+                    draw_level(s, &self.layout, self.lvl, &self.pal, false);
+                    if self.prefs.animations {
+                        self.level_flash = Some(Instant::now());
+                    }
+                    log::debug!("now at lvl {}", self.lvl);
+                }
+
+                s.flush().unwrap();
+            },
+            Output::ShapeLocked(shape, board) => {
+                log::info!("[{}] shape locked: {:?}", self.n, shape);
+                // the piece is part of the board now, and came to rest where
+                // its ghost was
+                self.clearing = None;
+                self.settled = *board;
+                self.active = None;
+                self.ghost_at = None;
+                self.sync(s);
+            },
+            Output::NextShape(shape) => {
+                let l = self.layout;
+                if let Some(p) = self.next_pos {
+                    pos(s, p);
+                    sgr(s, &self.pal.text);
+                    clr(s, 11);
+                }
+                if let Some(old) = self.next_shape {
+                    let g = self.glyph(old);
+                    clear_glyph(s, &g, Point::new(l.panel_x, l.next_bottom - g.height()), &self.pal.text);
+                }
+                self.draw_next(s, *shape);
+            },
+            Output::ShapePosition(shape, _, orientation, _, to) => {
+                log::debug!("[{}] shape position: {:?}, {:?}, {:?}", self.n, shape, orientation, to);
+                self.active = Some((*shape, *orientation, *to));
+                self.ghost_at = if self.prefs.ghost {
+                    Some(self.ghost_point(&shapewrap::cells(*shape, *orientation), *to))
+                } else {
+                    None
+                };
+                self.sync(s);
+                s.flush().unwrap();
+            },
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tetrix::shape::Point;
+use crate::draw::*;
+use crate::replay;
+use crate::layout::Layout;
+use crate::view::GameView;
+use crate::prefs::Prefs;
+use crate::transport::Transport;
+
+const PAGE: usize = 10;
+const SPEEDS: [f64; 4] = [0.5, 1.0, 2.0, 4.0];
+const SEEK_MS: u64 = 10_000;
+
+fn mmss(ms: u64) -> String {
+    format!("{:02}:{:02}", ms / 60_000, (ms / 1000) % 60)
+}
+
+/// The "Watch replays" menu: the most recent or the highest scoring
+/// replays, one of which can be picked to play back.
+pub struct Menu {
+    top: bool,
+    entries: Vec<(PathBuf, replay::ReplayMeta)>
+}
+
+impl Menu {
+    pub fn new() -> Menu {
+        Menu { top: false, entries: Vec::new() }
+    }
+
+    /// Lists the replays as they are now.
+    pub fn draw(&mut self, s: &mut dyn Transport) {
+        self.entries = if self.top { replay::top(PAGE) } else { replay::recent(PAGE) };
+        cls(s);
+        pos(s, Point::new(1,1));
+        if self.top {
+            s.write(b"Top replays\r\n\r\n").unwrap();
+        } else {
+            s.write(b"Recent replays\r\n\r\n").unwrap();
+        }
+        if self.entries.is_empty() {
+            s.write(b"No replays yet.\r\n").unwrap();
+        }
+        for (i, (_, meta)) in self.entries.iter().enumerate() {
+            let who = if meta.guest { format!("{} (guest)", meta.player) } else { meta.player.clone() };
+            s.write(format!("[{}] {:<24} {:>5} lines  {}  {}\r\n",
+                i, who, meta.score, mmss(meta.duration), meta.mode).as_bytes()).unwrap();
+        }
+        s.write(b"\r\n[0-9] watch  [r]ecent  [t]op  [q] back\r\n").unwrap();
+        s.flush().unwrap();
+    }
+
+    /// Switches lists, or picks a replay to watch.
+    pub fn key(&mut self, s: &mut dyn Transport, c: u8) -> Option<&Path> {
+        match c {
+            b'r' | b't' => {
+                self.top = c == b't';
+                self.draw(s);
+                None
+            },
+            b'0'..=b'9' => self.entries.get((c - b'0') as usize).map(|(path, _)| path.as_path()),
+            _ => None
+        }
+    }
+}
+
+fn draw_status(s: &mut dyn Transport, l: &Layout, meta: &replay::ReplayMeta, clock: u64, speed: f64, paused: bool, ended: bool) {
+    let state = if ended { "END" } else if paused { "PAUSED" } else { "" };
+    pos(s, Point::new(l.panel_x, l.status_row));
+    s.write(format!("{:<30}", format!("Replay: {}", meta.player)).as_bytes()).unwrap();
+    pos(s, Point::new(l.panel_x, l.status_row + 1));
+    s.write(format!("{:<30}", format!("Mode: {}  Lines: {}", meta.mode, meta.score)).as_bytes()).unwrap();
+    pos(s, Point::new(l.panel_x, l.status_row + 3));
+    s.write(format!("{:<30}", format!("{}/{}  {}x  {}",
+        mmss(clock.min(meta.duration)), mmss(meta.duration), speed, state)).as_bytes()).unwrap();
+    pos(s, Point::new(l.panel_x, l.status_row + 5));
+    s.write(b"[space] pause   [n] step").unwrap();
+    pos(s, Point::new(l.panel_x, l.status_row + 6));
+    s.write(b"[+/-] speed     [b/f] seek").unwrap();
+    pos(s, Point::new(l.panel_x, l.status_row + 7));
+    s.write(b"[q] back to the list").unwrap();
+}
+
+/// Plays a replay back through `GameView` on a virtual clock.  Seeking
+/// backwards re-renders from the first event since the drawing is
+/// incremental.
+pub struct Playback {
+    r: replay::Replay,
+    prefs: Prefs,
+    view: GameView,
+    speed: usize,
+    paused: bool,
+    clock: u64,
+    next: usize,
+    last: Instant,
+    dirty: bool,
+    shown: String
+}
+
+impl Playback {
+    pub fn load(path: &Path, prefs: &Prefs) -> Option<Playback> {
+        let r = match replay::load(path) {
+            Ok(r) => r,
+            Err(e) => {
+                log::error!("could not load replay {:?}: {}", path, e);
+                return None;
+            }
+        };
+        // the watcher's own display settings, the player's starting level
+        let prefs = Prefs { start_level: r.meta.start_level, ..prefs.clone() };
+        let view = GameView::new(&r.meta.player, &prefs);
+        Some(Playback {
+            r,
+            prefs,
+            view,
+            speed: 1,
+            paused: false,
+            clock: 0,
+            next: 0,
+            last: Instant::now(),
+            dirty: true,
+            shown: String::new()
+        })
+    }
+
+    /// Paints what has been played so far, for a fresh screen.
+    pub fn redraw(&mut self, s: &mut dyn Transport) {
+        self.view.redraw(s);
+        self.dirty = true;
+    }
+
+    /// Moves the clock on and draws whatever happened meanwhile.
+    pub fn tick(&mut self, s: &mut dyn Transport) {
+        let now = Instant::now();
+        if !self.paused {
+            self.clock += (now.duration_since(self.last).as_millis() as f64 * SPEEDS[self.speed]) as u64;
+        }
+        self.last = now;
+
+        while self.next < self.r.events.len() && self.r.events[self.next].0 <= self.clock {
+            self.view.handle(s, &self.r.events[self.next].1);
+            self.next += 1;
+            self.dirty = true;
+        }
+
+        self.view.tick(s);
+
+        let ended = self.next == self.r.events.len();
+        let status = format!("{} {} {} {}", self.clock / 1000, self.speed, self.paused, ended);
+        if self.dirty || status != self.shown {
+            draw_status(s, &self.view.layout, &self.r.meta, self.clock, SPEEDS[self.speed], self.paused, ended);
+            s.flush().unwrap();
+            self.shown = status;
+            self.dirty = false;
+        }
+    }
+
+    /// Pause, speed, step and seek.
+    pub fn key(&mut self, s: &mut dyn Transport, c: u8) {
+        match c {
+            b' ' => self.paused = !self.paused,
+            b'+' | b'=' => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+            b'-' => self.speed = self.speed.saturating_sub(1),
+            b'n' => {
+                if self.paused && self.next < self.r.events.len() {
+                    self.clock = self.r.events[self.next].0;
+                }
+            },
+            b'f' => self.clock += SEEK_MS,
+            b'b' => {
+                self.clock = self.clock.saturating_sub(SEEK_MS);
+                self.view = GameView::new(&self.r.meta.player, &self.prefs);
+                self.next = 0;
+                cls(s);
+                self.dirty = true;
+            },
+            _ => {}
+        }
+    }
+}
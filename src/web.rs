@@ -0,0 +1,192 @@
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::mem;
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use tungstenite::{Message, WebSocket};
+use crate::session;
+use crate::telnet;
+use crate::transport::Transport;
+
+pub const PORT: u16 = 8080;
+// the page and the terminal it runs; xterm.js and xterm.css come from the
+// xterm package, fetched by web/fetch-xterm.sh
+const ROOT: &str = "web";
+const FILES: &[(&str, &str, &str)] = &[
+    ("/", "index.html", "text/html"),
+    ("/xterm.js", "xterm.js", "application/javascript"),
+    ("/xterm.css", "xterm.css", "text/css")
+];
+
+/// A browser terminal on the other end of a WebSocket.  Keys come in
+/// binary messages, the screen goes out in them; text messages are JSON
+/// for anything else, so far `{"type": "resize", "cols": .., "rows": ..}`,
+/// which stands in for NAWS.
+struct WebTerm {
+    ws: WebSocket<TcpStream>,
+    keys: Vec<u8>,
+    out: Vec<u8>
+}
+
+impl WebTerm {
+    fn control(&self, text: &str) {
+        let v = match json::parse(text) {
+            Ok(v) => v,
+            Err(_) => return
+        };
+        if v["type"] == "resize" {
+            if let (Some(w), Some(h)) = (v["cols"].as_u16(), v["rows"].as_u16()) {
+                log::debug!("browser window is {}x{}", w, h);
+                telnet::set_window_size((w, h));
+            }
+        }
+    }
+}
+
+impl BufRead for WebTerm {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.keys.is_empty() {
+            match self.ws.read() {
+                Ok(Message::Binary(data)) => self.keys = data.to_vec(),
+                Ok(Message::Text(text)) => self.control(text.as_str()),
+                Ok(Message::Close(_)) => return Err(io::ErrorKind::ConnectionAborted.into()),
+                Ok(_) => {},
+                Err(tungstenite::Error::Io(e)) => return Err(e),
+                // closed, or something we can't make sense of; either way
+                // there's nothing more to read
+                Err(_) => return Err(io::ErrorKind::ConnectionAborted.into())
+            }
+        }
+        Ok(&self.keys)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.keys.drain(..amt);
+    }
+}
+
+impl Read for WebTerm {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let rest = self.fill_buf()?;
+            let n = rest.len().min(buf.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl Write for WebTerm {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.out.is_empty() {
+            let data = mem::take(&mut self.out);
+            if let Err(e) = self.ws.send(Message::binary(data)) {
+                return would_block(e);
+            }
+        }
+        // the socket doesn't block, so a big screen may go out in pieces
+        loop {
+            match self.ws.flush() {
+                Ok(()) => return Ok(()),
+                Err(tungstenite::Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(10));
+                },
+                Err(e) => return would_block(e)
+            }
+        }
+    }
+}
+
+impl Transport for WebTerm {}
+
+// a send that was queued rather than refused is fine; flush finishes it
+fn would_block(e: tungstenite::Error) -> io::Result<()> {
+    match e {
+        tungstenite::Error::Io(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+        tungstenite::Error::Io(e) => Err(e),
+        _ => Err(io::ErrorKind::BrokenPipe.into())
+    }
+}
+
+// the request line and headers, left in the socket for the handshake
+fn request_head(stream: &TcpStream) -> Option<String> {
+    let mut buf = [0; 4096];
+    for _ in 0..100 {
+        let n = stream.peek(&mut buf).ok()?;
+        if n == 0 {
+            return None;
+        }
+        let head = String::from_utf8_lossy(&buf[..n]);
+        if let Some(end) = head.find("\r\n\r\n") {
+            return Some(head[..end].to_string());
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    None
+}
+
+fn send_file(mut stream: TcpStream, head: &str) {
+    let path = head.split_whitespace().nth(1).unwrap_or("/");
+    let found = FILES.iter()
+        .find(|(url, _, _)| *url == path)
+        .and_then(|(_, file, kind)| Some((fs::read(Path::new(ROOT).join(file)).ok()?, kind)));
+    let _ = match found {
+        Some((body, kind)) => stream.write_all(format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            kind, body.len()).as_bytes()).and_then(|_| stream.write_all(&body)),
+        None => stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+    };
+}
+
+fn serve(stream: TcpStream) {
+    let head = match request_head(&stream) {
+        Some(head) => head,
+        None => return
+    };
+    if !head.to_lowercase().contains("upgrade: websocket") {
+        send_file(stream, &head);
+        return;
+    }
+    let ws = match tungstenite::accept(stream) {
+        Ok(ws) => ws,
+        Err(_) => {
+            log::info!("websocket handshake failed");
+            return;
+        }
+    };
+    ws.get_ref().set_nonblocking(true).unwrap();
+    log::info!("New browser connection. {:?}", ws.get_ref().peer_addr());
+    session::run(Box::new(WebTerm { ws, keys: Vec::new(), out: Vec::new() }), None);
+}
+
+/// Serves the browser page and its WebSocket on `PORT` until the process
+/// ends, a thread per connection as for telnet.
+pub fn listen() {
+    let listener = match TcpListener::bind(("0.0.0.0", PORT)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("not serving the web page on port {}: {}", PORT, e);
+            return;
+        }
+    };
+    for (_, file, _) in FILES {
+        if !Path::new(ROOT).join(file).exists() {
+            log::error!("{}/{} is missing; run web/fetch-xterm.sh", ROOT, file);
+        }
+    }
+    log::info!("Starting web service on 0.0.0.0 at port {}", PORT);
+    for stream in listener.incoming() {
+        if let Ok(stream) = stream {
+            thread::spawn(move || serve(stream));
+        }
+    }
+}
@@ -0,0 +1,9 @@
+#!/bin/sh
+# Fetches the terminal the web page runs, at the version it was written
+# against, into this directory.  Run it once before serving the page.
+set -e
+VERSION=5.3.0
+cd "$(dirname "$0")"
+curl -fsSL -o xterm.js "https://unpkg.com/xterm@$VERSION/lib/xterm.js"
+curl -fsSL -o xterm.css "https://unpkg.com/xterm@$VERSION/css/xterm.css"
+echo "xterm $VERSION fetched into $(pwd)"